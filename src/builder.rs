@@ -0,0 +1,280 @@
+use crate::error::NcclError;
+use crate::options::ParseOptions;
+use crate::pair::{MergeStrategy, Pair};
+use crate::value::Value;
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::path::Path;
+
+/// A layer of configuration for a `ConfigBuilder`.
+///
+/// Examples:
+///
+/// ```
+/// use nccl::{ConfigBuilder, NcclError, Pair, Source};
+///
+/// struct Hostname;
+///
+/// impl Source for Hostname {
+///     fn name(&self) -> String {
+///         "hostname".into()
+///     }
+///
+///     fn load(&self) -> Result<Option<Pair>, Vec<NcclError>> {
+///         nccl::parse_string("server\n    domain\n        localhost").map(Some)
+///     }
+/// }
+///
+/// let config = ConfigBuilder::new()
+///     .add_file("examples/config.nccl")
+///     .add_source(Hostname)
+///     .build()
+///     .unwrap();
+/// assert_eq!(config["server"]["domain"].value().unwrap(), "localhost");
+/// ```
+pub trait Source {
+    /// A name for the source, used to tell where a value came from.
+    fn name(&self) -> String;
+
+    /// Loads the configuration, or `None` if there is nothing to load.
+    fn load(&self) -> Result<Option<Pair>, Vec<NcclError>>;
+}
+
+struct PairSource(Pair);
+
+impl Source for PairSource {
+    fn name(&self) -> String {
+        "defaults".into()
+    }
+
+    fn load(&self) -> Result<Option<Pair>, Vec<NcclError>> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+struct FileSource {
+    filename: String,
+    optional: bool,
+    options: ParseOptions,
+}
+
+impl Source for FileSource {
+    fn name(&self) -> String {
+        self.filename.clone()
+    }
+
+    fn load(&self) -> Result<Option<Pair>, Vec<NcclError>> {
+        if self.optional && !Path::new(&self.filename).exists() {
+            Ok(None)
+        } else {
+            crate::parse_file_with_options(&self.filename, &self.options).map(Some)
+        }
+    }
+}
+
+struct StringSource {
+    data: String,
+    options: ParseOptions,
+}
+
+impl Source for StringSource {
+    fn name(&self) -> String {
+        "string".into()
+    }
+
+    fn load(&self) -> Result<Option<Pair>, Vec<NcclError>> {
+        crate::parse_string_with_options(&self.data, &self.options).map(Some)
+    }
+}
+
+/// Builds one configuration out of layers, such as built-in defaults, then a
+/// system file, then a user file. Each layer is merged over the ones added
+/// before it, so later layers take precedence.
+///
+/// Examples:
+///
+/// ```
+/// use nccl::{ConfigBuilder, Pair, Value};
+///
+/// let mut defaults = Pair::new("__top_level__");
+/// defaults.add_slice(&[Value::from("threads"), Value::from(4)]);
+///
+/// let config = ConfigBuilder::new()
+///     .add_default(defaults)
+///     .add_file("examples/config.nccl")
+///     .add_optional_file("examples/missing.nccl")
+///     .add_string("threads\n    16")
+///     .build()
+///     .unwrap();
+/// assert_eq!(config["threads"].value_as::<i64>().unwrap(), 16);
+/// assert_eq!(config.source_of(&[Value::from("threads")]), Some("string"));
+/// assert_eq!(config.source_of(&[Value::from("server")]), Some("examples/config.nccl"));
+/// ```
+pub struct ConfigBuilder {
+    sources: Vec<Box<dyn Source>>,
+    strategy: MergeStrategy,
+    options: ParseOptions,
+}
+
+impl ConfigBuilder {
+    /// Creates a builder with no layers, which replaces the values of a key
+    /// when a later layer sets it again.
+    pub fn new() -> Self {
+        ConfigBuilder {
+            sources: vec![],
+            strategy: MergeStrategy::ReplaceLeaves,
+            options: ParseOptions::default(),
+        }
+    }
+
+    /// Sets how each layer is merged over the ones before it.
+    pub fn strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the parse options used by the files and strings added after it.
+    pub fn options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Adds a layer of configuration that has already been parsed or built.
+    pub fn add_default(self, pair: Pair) -> Self {
+        self.add_source(PairSource(pair))
+    }
+
+    /// Adds a file, which must exist.
+    pub fn add_file(self, filename: &str) -> Self {
+        let options = self.options.clone();
+        self.add_source(FileSource {
+            filename: filename.into(),
+            optional: false,
+            options,
+        })
+    }
+
+    /// Adds a file that is skipped if it doesn't exist. Errors in a file that
+    /// does exist are still reported.
+    pub fn add_optional_file(self, filename: &str) -> Self {
+        let options = self.options.clone();
+        self.add_source(FileSource {
+            filename: filename.into(),
+            optional: true,
+            options,
+        })
+    }
+
+    /// Adds raw string data.
+    pub fn add_string(self, data: &str) -> Self {
+        let options = self.options.clone();
+        self.add_source(StringSource {
+            data: data.into(),
+            options,
+        })
+    }
+
+    /// Adds a custom source.
+    pub fn add_source<S: Source + 'static>(mut self, source: S) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Loads every layer in order and merges them. Errors from every layer
+    /// are reported together.
+    pub fn build(&self) -> Result<Config, Vec<NcclError>> {
+        let mut config = Config {
+            pair: Pair::new("__top_level__"),
+            layers: vec![],
+            origins: HashMap::new(),
+        };
+        let mut errors = vec![];
+
+        for source in &self.sources {
+            let pair = match source.load() {
+                Ok(Some(pair)) => pair,
+                Ok(None) => continue,
+                Err(mut e) => {
+                    errors.append(&mut e);
+                    continue;
+                }
+            };
+
+            let layer = config.layers.len();
+            config.layers.push(source.name());
+            let set: HashSet<String> = pair.paths().iter().map(|p| origin_key(p)).collect();
+
+            if let Err(e) = config.pair.merge(pair, self.strategy) {
+                errors.push(e);
+            }
+
+            // paths the merge replaced or removed are no longer in the config,
+            // so they're dropped along with their old layer
+            let mut origins = HashMap::new();
+            for path in config.pair.paths() {
+                let key = origin_key(&path);
+                let origin = if set.contains(&key) {
+                    Some(layer)
+                } else {
+                    config.origins.get(&key).copied()
+                };
+                if let Some(origin) = origin {
+                    origins.insert(key, origin);
+                }
+            }
+            config.origins = origins;
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        ConfigBuilder::new()
+    }
+}
+
+/// Configuration built by a `ConfigBuilder`, which remembers the layer each
+/// key came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pair: Pair,
+    // the name of each layer that was loaded, and the last layer to set each
+    // path still in the configuration
+    layers: Vec<String>,
+    origins: HashMap<String, usize>,
+}
+
+// Value can't be hashed because of floats, but its Debug form tells apart
+// every path, including `1` and `"1"`
+fn origin_key(path: &[Value]) -> String {
+    format!("{:?}", path)
+}
+
+impl Config {
+    /// The name of the last layer that set a key or value, such as the name
+    /// of its file, or `None` if it isn't in the configuration.
+    pub fn source_of(&self, path: &[Value]) -> Option<&str> {
+        self.origins
+            .get(&origin_key(path))
+            .map(|&layer| self.layers[layer].as_str())
+    }
+
+    /// Takes the configuration out of the builder's result.
+    pub fn into_pair(self) -> Pair {
+        self.pair
+    }
+}
+
+impl Deref for Config {
+    type Target = Pair;
+    fn deref(&self) -> &Pair {
+        &self.pair
+    }
+}
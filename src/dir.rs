@@ -0,0 +1,52 @@
+use crate::error::{ErrorKind, NcclError};
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Lists the files in a directory whose names match a pattern, in lexical
+/// order. The pattern may use `*` for any run of characters and `?` for any
+/// single character.
+pub(crate) fn matching_files(
+    dir: &str,
+    pattern: &str,
+    skip_hidden: bool,
+) -> Result<Vec<PathBuf>, NcclError> {
+    let error = || NcclError::new(ErrorKind::File, "Could not read directory.", 0).in_file(dir);
+
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).map_err(|_| error())? {
+        let entry = entry.map_err(|_| error())?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.path().is_file()
+            || !glob(pattern.as_bytes(), name.as_bytes())
+            || (skip_hidden && is_hidden(&name))
+        {
+            continue;
+        }
+        files.push(entry.path());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+// dotfiles, and the backup and lock files editors leave behind
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+        || name.ends_with('~')
+        || name.ends_with(".bak")
+        || name.ends_with(".swp")
+        || (name.starts_with('#') && name.ends_with('#'))
+}
+
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
@@ -0,0 +1,41 @@
+use crate::pair::Pair;
+
+use std::ops::Deref;
+
+/// One document from a source containing several, separated by `---` lines.
+///
+/// Examples:
+///
+/// ```
+/// let documents = nccl::parse_file_documents("examples/tenants.nccl").unwrap();
+/// assert_eq!(documents[1]["tenant"].value().unwrap(), "globex");
+/// assert_eq!(documents[1].line(), 8);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    pair: Pair,
+    line: u64,
+}
+
+impl Document {
+    pub(crate) fn new(pair: Pair, line: u64) -> Self {
+        Document { pair, line }
+    }
+
+    /// The line of the source this document starts on.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Takes the configuration out of the document.
+    pub fn into_pair(self) -> Pair {
+        self.pair
+    }
+}
+
+impl Deref for Document {
+    type Target = Pair;
+    fn deref(&self) -> &Pair {
+        &self.pair
+    }
+}
@@ -0,0 +1,96 @@
+use crate::error::{ErrorKind, NcclError};
+
+// the byte order marks that editors put at the start of a file
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+const UTF32LE_BOM: &[u8] = &[0xFF, 0xFE, 0x00, 0x00];
+const UTF32BE_BOM: &[u8] = &[0x00, 0x00, 0xFE, 0xFF];
+
+/// Decodes the contents of a config file into a string. A UTF-8 byte order
+/// mark is removed, UTF-16 with a byte order mark is converted, and anything
+/// else that isn't UTF-8 is an error naming the encoding it looks like.
+pub(crate) fn decode(bytes: Vec<u8>) -> Result<String, NcclError> {
+    if bytes.starts_with(UTF32LE_BOM) {
+        Err(unsupported("UTF-32LE", 1))
+    } else if bytes.starts_with(UTF32BE_BOM) {
+        Err(unsupported("UTF-32BE", 1))
+    } else if bytes.starts_with(UTF16LE_BOM) {
+        utf16(&bytes[2..], "UTF-16LE", u16::from_le_bytes)
+    } else if bytes.starts_with(UTF16BE_BOM) {
+        utf16(&bytes[2..], "UTF-16BE", u16::from_be_bytes)
+    } else if bytes.starts_with(UTF8_BOM) {
+        utf8(bytes[3..].to_vec())
+    } else {
+        utf8(bytes)
+    }
+}
+
+fn utf8(bytes: Vec<u8>) -> Result<String, NcclError> {
+    String::from_utf8(bytes).map_err(|err| {
+        let bytes = err.as_bytes();
+        let valid = err.utf8_error().valid_up_to();
+        let line = bytes[..valid].iter().filter(|&&c| c == b'\n').count() as u64 + 1;
+
+        // text without a byte order mark is usually UTF-16 if every other
+        // byte is zero, and a legacy 8-bit code page otherwise
+        let zeros = |parity| {
+            bytes
+                .iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|&&c| c == 0)
+                .count()
+        };
+        let half = bytes.len() / 4;
+        if zeros(1) > half {
+            unsupported("UTF-16LE without a byte order mark", line)
+        } else if zeros(0) > half {
+            unsupported("UTF-16BE without a byte order mark", line)
+        } else {
+            unsupported("an 8-bit encoding such as Windows-1252", line)
+        }
+    })
+}
+
+fn utf16(
+    bytes: &[u8],
+    encoding: &str,
+    from_bytes: fn([u8; 2]) -> u16,
+) -> Result<String, NcclError> {
+    let invalid = || {
+        NcclError::new(
+            ErrorKind::Encoding {
+                encoding: encoding.to_string(),
+            },
+            &format!(
+                "File starts with a {} byte order mark but is not valid {}",
+                encoding, encoding
+            ),
+            0,
+        )
+    };
+
+    if !bytes.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).map_err(|_| invalid())
+}
+
+fn unsupported(encoding: &str, line: u64) -> NcclError {
+    NcclError::new(
+        ErrorKind::Encoding {
+            encoding: encoding.to_string(),
+        },
+        &format!(
+            "File is encoded as {}, not UTF-8. Save it as UTF-8 and try again.",
+            encoding
+        ),
+        line,
+    )
+}
@@ -0,0 +1,138 @@
+use crate::builder::Source;
+use crate::error::NcclError;
+use crate::pair::Pair;
+use crate::value::{parse_into_value, Value};
+
+use std::env;
+
+/// Configuration from environment variables. A variable such as
+/// `MYAPP_SERVER__PORT=8080` becomes `8080` under `port` under `server`: the
+/// prefix is removed, the rest is split into keys at each separator and the
+/// keys are lowercased. Values are inferred like values in a file, and a
+/// value containing commas becomes a list.
+///
+/// Examples:
+///
+/// ```
+/// use nccl::{EnvSource, MergeStrategy};
+///
+/// let env = EnvSource::new("MYAPP_").vars(vec![
+///     ("MYAPP_SERVER__PORT".into(), "8080".into()),
+///     ("MYAPP_SERVER__DOMAIN".into(), "example.com, www.example.com".into()),
+///     ("PATH".into(), "/usr/bin".into()),
+/// ]);
+///
+/// let mut config = nccl::parse_file("examples/config.nccl").unwrap();
+/// config.merge(env.to_pair(), MergeStrategy::ReplaceLeaves).unwrap();
+/// assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+/// assert_eq!(config["server"]["domain"].keys_as::<String>().unwrap().len(), 2);
+/// assert!(!config.has_key("path"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvSource {
+    prefix: String,
+    separator: String,
+    lowercase: bool,
+    lists: bool,
+    vars: Option<Vec<(String, String)>>,
+}
+
+impl EnvSource {
+    /// Creates a source for the variables whose names start with a prefix,
+    /// such as `MYAPP_`.
+    pub fn new(prefix: &str) -> Self {
+        EnvSource {
+            prefix: prefix.into(),
+            separator: "__".into(),
+            lowercase: true,
+            lists: true,
+            vars: None,
+        }
+    }
+
+    /// Sets what separates nested keys in a variable name. The default is
+    /// `__`, so that single underscores can be part of a key.
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Sets whether keys are lowercased. On by default.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Sets whether values containing commas are split into lists. On by
+    /// default.
+    pub fn lists(mut self, lists: bool) -> Self {
+        self.lists = lists;
+        self
+    }
+
+    /// Reads these variables instead of the environment of the process.
+    pub fn vars(mut self, vars: Vec<(String, String)>) -> Self {
+        self.vars = Some(vars);
+        self
+    }
+
+    /// Builds the configuration from the variables.
+    pub fn to_pair(&self) -> Pair {
+        let mut vars = match &self.vars {
+            Some(vars) => vars.clone(),
+            None => env::vars().collect(),
+        };
+        vars.sort();
+
+        let mut pair = Pair::new("__top_level__");
+        for (name, value) in vars {
+            let name = match name.strip_prefix(&self.prefix) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            // names with an empty key, like `MYAPP_` or `MYAPP_A____B`, don't
+            // map onto a path
+            let keys: Vec<&str> = name.split(self.separator.as_str()).collect();
+            if keys.iter().any(|key| key.is_empty()) {
+                continue;
+            }
+            let mut path: Vec<Value> = keys
+                .iter()
+                .map(|&key| {
+                    if self.lowercase {
+                        parse_into_value(key.to_lowercase())
+                    } else {
+                        parse_into_value(key.into())
+                    }
+                })
+                .collect();
+
+            pair.traverse_path(&path);
+            let items: Vec<&str> = if self.lists && value.contains(',') {
+                value.split(',').map(str::trim).collect()
+            } else {
+                vec![value.as_str()]
+            };
+            for item in items {
+                if !item.is_empty() {
+                    path.push(parse_into_value(item.into()));
+                    pair.add_slice(&path);
+                    path.pop();
+                }
+            }
+        }
+
+        pair
+    }
+}
+
+impl Source for EnvSource {
+    fn name(&self) -> String {
+        format!("environment ({}*)", self.prefix)
+    }
+
+    fn load(&self) -> Result<Option<Pair>, Vec<NcclError>> {
+        Ok(Some(self.to_pair()))
+    }
+}
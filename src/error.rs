@@ -13,6 +13,12 @@ pub enum ErrorKind {
     FromStr,
     File,
     Utf8 { err: std::string::FromUtf8Error },
+    Encoding { encoding: String },
+    Include,
+    Conflict,
+    Override,
+    Interpolation,
+    Reference,
     Io,
 }
 
@@ -22,6 +28,9 @@ pub struct NcclError {
     kind: ErrorKind,
     line: u64,
     message: String,
+    file: Option<String>,
+    // the files and lines that included `file`, innermost first
+    included_from: Vec<(String, u64)>,
 }
 
 impl NcclError {
@@ -29,7 +38,11 @@ impl NcclError {
     pub fn new(kind: ErrorKind, message: &str, line: u64) -> Self {
         NcclError {
             message: match kind {
-                ErrorKind::Parse | ErrorKind::Indentation => format!(
+                ErrorKind::Parse
+                | ErrorKind::Indentation
+                | ErrorKind::Include
+                | ErrorKind::Interpolation
+                | ErrorKind::Reference => format!(
                     "An error has ocurred: {:?} on line {}\n\t{}",
                     kind, line, message
                 ),
@@ -37,23 +50,64 @@ impl NcclError {
             },
             kind,
             line,
+            file: None,
+            included_from: vec![],
         }
     }
+
+    /// The file the error occurred in, if it came from a file.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let errors = nccl::parse_file("examples/tabs.nccl").unwrap_err();
+    /// assert_eq!(errors[0].file(), Some("examples/tabs.nccl"));
+    /// ```
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    // records the file an error occurred in, unless an included file
+    // already has
+    pub(crate) fn in_file(mut self, file: &str) -> Self {
+        if self.file.is_none() {
+            self.file = Some(file.to_string());
+        }
+        self
+    }
+
+    pub(crate) fn included_from(mut self, file: &str, line: u64) -> Self {
+        self.included_from.push((file.to_string(), line));
+        self
+    }
 }
 
 impl fmt::Display for NcclError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.kind {
-            ErrorKind::Parse | ErrorKind::Indentation => write!(
+            ErrorKind::Parse
+            | ErrorKind::Indentation
+            | ErrorKind::Include
+            | ErrorKind::Interpolation
+            | ErrorKind::Reference => write!(
                 f,
                 "An error has ocurred: {:?} on line {}\n\t{}",
                 self.kind, self.line, self.message
-            ),
+            )?,
             _ => write!(
                 f,
                 "An error has ocurred: {:?}\n\t{}",
                 self.kind, self.message
-            ),
+            )?,
+        }
+
+        if let Some(file) = &self.file {
+            write!(f, "\n\tin {}", file)?;
         }
+        for (file, line) in &self.included_from {
+            write!(f, "\n\tincluded from {} on line {}", file, line)?;
+        }
+
+        Ok(())
     }
 }
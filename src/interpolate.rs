@@ -0,0 +1,106 @@
+use std::env;
+
+#[derive(Debug, PartialEq, Clone)]
+/// Where the variables in `${VAR}` come from when interpolation is turned on
+/// with `ParseOptions::interpolation`.
+pub enum Variables {
+    /// The environment of the process.
+    Environment,
+    /// A fixed list of names and values, useful for tests.
+    Fixed(Vec<(String, String)>),
+}
+
+impl Variables {
+    /// Looks up a variable.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::Variables;
+    /// let vars = Variables::Fixed(vec![("USER".into(), "ferris".into())]);
+    /// assert_eq!(vars.get("USER"), Some("ferris".into()));
+    /// assert_eq!(vars.get("HOME"), None);
+    /// ```
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self {
+            Variables::Environment => env::var(name).ok(),
+            Variables::Fixed(vars) => vars
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.clone()),
+        }
+    }
+}
+
+// replaces `${VAR}`, `${VAR:-default}` and `${VAR:?message}` in text, with
+// `$$` for a literal `$`. the error is the reason interpolation failed
+pub(crate) fn interpolate(text: &str, vars: &Variables) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(dollar) = rest.find('$') {
+        result.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+
+        let inner = match rest.strip_prefix('{') {
+            Some(inner) => inner,
+            None => {
+                result.push('$');
+                continue;
+            }
+        };
+        let end = inner
+            .find('}')
+            .ok_or_else(|| format!("Unterminated ${{ in {:?}", text))?;
+        let expression = &inner[..end];
+        rest = &inner[end + 1..];
+
+        // the first } would end the outer expression early, so `${A:-${B}}`
+        // is rejected rather than half expanded
+        if expression.contains("${") {
+            return Err(format!(
+                "Defaults and messages can't contain ${{...}} in {:?}",
+                text
+            ));
+        }
+
+        let (name, fallback) = match expression.find(':') {
+            Some(colon) => (&expression[..colon], Some(&expression[colon + 1..])),
+            None => (expression, None),
+        };
+
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("Invalid variable name {:?} in {:?}", name, text));
+        }
+
+        let value = vars.get(name);
+        match fallback {
+            None => match value {
+                Some(value) => result.push_str(&value),
+                None => return Err(format!("{} is not set", name)),
+            },
+
+            // like the shell, an empty variable counts as unset here
+            Some(fallback) => match (value.filter(|v| !v.is_empty()), fallback.chars().next()) {
+                (Some(value), Some('-')) | (Some(value), Some('?')) => result.push_str(&value),
+                (None, Some('-')) => result.push_str(&fallback[1..]),
+                (None, Some('?')) if fallback.len() > 1 => {
+                    return Err(format!("{}: {}", name, &fallback[1..]))
+                }
+                (None, Some('?')) => return Err(format!("{} is not set", name)),
+                _ => return Err(format!("Expected :- or :? after {} in {:?}", name, text)),
+            },
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
@@ -8,16 +8,29 @@
 //! people who just want stuff to do things. In nccl's case, simply inferring
 //! the data type is a great middle ground between user and developer comfort.
 
+mod builder;
+mod dir;
+mod document;
+mod encoding;
+mod env;
 mod error;
+mod interpolate;
 mod macros;
+mod options;
+mod overrides;
 mod pair;
 mod parser;
 mod scanner;
 mod token;
 mod value;
 
+pub use builder::*;
+pub use document::*;
+pub use env::*;
 pub use error::*;
-pub use macros::*;
+pub use interpolate::Variables;
+pub use options::*;
+pub use overrides::*;
 pub use pair::*;
 pub use value::*;
 
@@ -38,18 +51,31 @@ use std::path::Path;
 /// assert_eq!(ports, vec![80, 443]);
 /// ```
 pub fn parse_file(filename: &str) -> Result<Pair, Vec<NcclError>> {
-    if let Ok(mut file) = File::open(Path::new(filename)) {
-        let mut data = String::new();
-        file.read_to_string(&mut data)
-            .map_err(|_| vec![NcclError::new(ErrorKind::Io, "IO error", 0)])?;
-        Parser::new(Scanner::new(data).scan_tokens()?).parse()
-    } else {
-        Err(vec![NcclError::new(
-            ErrorKind::File,
-            "Could not find file.",
-            0,
-        )])
-    }
+    parse_file_with_options(filename, &ParseOptions::default())
+}
+
+/// Parses a file using the given filename and parse options.
+///
+/// Examples:
+///
+/// ```
+/// let options = nccl::ParseOptions::new();
+/// let config = nccl::parse_file_with_options("examples/numbers.nccl", &options).unwrap();
+/// assert_eq!(config["mode"].value_as::<i64>().unwrap(), 0o755);
+/// ```
+pub fn parse_file_with_options(
+    filename: &str,
+    options: &ParseOptions,
+) -> Result<Pair, Vec<NcclError>> {
+    let data = read_file(filename)?;
+    Scanner::new(data, options.clone())
+        .scan_tokens()
+        .and_then(|tokens| {
+            Parser::new(tokens, options.clone())
+                .in_file(filename)
+                .parse()
+        })
+        .map_err(|errors| in_file(errors, filename))
 }
 
 /// Parses a file, merging the results with the supplied pair. Allows for a
@@ -64,17 +90,77 @@ pub fn parse_file(filename: &str) -> Result<Pair, Vec<NcclError>> {
 /// assert_eq!(user["hello"]["world"].keys_as::<String>().unwrap().len(), 3);
 /// ```
 pub fn parse_file_with(filename: &str, pair: Pair) -> Result<Pair, Vec<NcclError>> {
-    if let Ok(mut file) = File::open(Path::new(filename)) {
-        let mut data = String::new();
-        file.read_to_string(&mut data)
-            .map_err(|_| vec![NcclError::new(ErrorKind::Io, "IO error", 0)])?;
-        Parser::new_with(Scanner::new(data).scan_tokens()?, pair).parse()
+    let data = read_file(filename)?;
+    Scanner::new(data, ParseOptions::default())
+        .scan_tokens()
+        .and_then(|tokens| {
+            Parser::new_with(tokens, pair, ParseOptions::default())
+                .in_file(filename)
+                .parse()
+        })
+        .map_err(|errors| in_file(errors, filename))
+}
+
+/// Parses every file in a directory whose name matches a pattern, such as
+/// `*.nccl`, in lexical order. Each file is merged over the ones before it
+/// with `MergeStrategy::ReplaceLeaves`, so a later file replaces the values
+/// an earlier one set. Dotfiles and editor backup files are skipped.
+///
+/// Examples:
+///
+/// ```
+/// let config = nccl::parse_dir("examples/conf.d", "*.nccl").unwrap();
+/// assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+/// assert_eq!(config["log"].value().unwrap(), "debug");
+/// ```
+pub fn parse_dir(path: &str, pattern: &str) -> Result<Pair, Vec<NcclError>> {
+    parse_dir_with_options(path, pattern, &ParseOptions::default())
+}
+
+/// Parses every file in a directory whose name matches a pattern using the
+/// given parse options. Errors from every file are reported together, each
+/// with the name of its file.
+///
+/// Examples:
+///
+/// ```
+/// let options = nccl::ParseOptions::new().skip_hidden_files(false);
+/// let config = nccl::parse_dir_with_options("examples/conf.d", ".*.nccl", &options).unwrap();
+/// assert_eq!(config["log"].value().unwrap(), "trace");
+/// ```
+pub fn parse_dir_with_options(
+    path: &str,
+    pattern: &str,
+    options: &ParseOptions,
+) -> Result<Pair, Vec<NcclError>> {
+    let files =
+        dir::matching_files(path, pattern, options.skip_hidden_files).map_err(|err| vec![err])?;
+
+    let mut pair = Pair::new("__top_level__");
+    let mut errors = vec![];
+    for file in files {
+        let filename = file.to_string_lossy();
+        let result = read_file(&filename)
+            .and_then(|data| Scanner::new(data, options.clone()).scan_tokens())
+            .and_then(|tokens| {
+                Parser::new(tokens, options.clone())
+                    .in_file(&filename)
+                    .parse()
+            })
+            .and_then(|file_pair| {
+                pair.merge(file_pair, MergeStrategy::ReplaceLeaves)
+                    .map_err(|err| vec![err])
+            });
+
+        if let Err(e) = result {
+            errors.append(&mut in_file(e, &filename));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(pair)
     } else {
-        Err(vec![NcclError::new(
-            ErrorKind::File,
-            "Could not find file.",
-            0,
-        )])
+        Err(errors)
     }
 }
 
@@ -87,5 +173,106 @@ pub fn parse_file_with(filename: &str, pair: Pair) -> Result<Pair, Vec<NcclError
 /// assert_eq!(raw["hello"].value_as::<String>().unwrap(), "world!");
 /// ```
 pub fn parse_string(data: &str) -> Result<Pair, Vec<NcclError>> {
-    Parser::new(Scanner::new(data.to_owned()).scan_tokens()?).parse()
+    parse_string_with_options(data, &ParseOptions::default())
+}
+
+/// Parses raw string data using the given parse options.
+///
+/// Examples:
+///
+/// ```
+/// let options = nccl::ParseOptions::new();
+/// let raw = nccl::parse_string_with_options("mask\n\t0xFF", &options).unwrap();
+/// assert_eq!(raw["mask"].value_as::<i64>().unwrap(), 255);
+/// ```
+pub fn parse_string_with_options(
+    data: &str,
+    options: &ParseOptions,
+) -> Result<Pair, Vec<NcclError>> {
+    Parser::new(
+        Scanner::new(data.to_owned(), options.clone()).scan_tokens()?,
+        options.clone(),
+    )
+    .parse()
+}
+
+/// Parses raw string data containing several documents separated by lines
+/// of `---`. Each document is parsed on its own, with its own indentation,
+/// and errors report their line in the whole source.
+///
+/// Examples:
+///
+/// ```
+/// let documents = nccl::parse_documents("a\n    1\n---\na\n    2\n").unwrap();
+/// assert_eq!(documents.len(), 2);
+/// assert_eq!(documents[1]["a"].value_as::<i64>().unwrap(), 2);
+/// ```
+pub fn parse_documents(data: &str) -> Result<Vec<Document>, Vec<NcclError>> {
+    documents(data.to_owned(), None, &ParseOptions::default())
+}
+
+/// Parses a file containing several documents separated by lines of `---`.
+///
+/// Examples:
+///
+/// ```
+/// let documents = nccl::parse_file_documents("examples/tenants.nccl").unwrap();
+/// assert_eq!(documents[0]["tenant"].value().unwrap(), "acme");
+/// ```
+pub fn parse_file_documents(filename: &str) -> Result<Vec<Document>, Vec<NcclError>> {
+    documents(
+        read_file(filename)?,
+        Some(filename),
+        &ParseOptions::default(),
+    )
+    .map_err(|errors| in_file(errors, filename))
+}
+
+fn documents(
+    data: String,
+    filename: Option<&str>,
+    options: &ParseOptions,
+) -> Result<Vec<Document>, Vec<NcclError>> {
+    let mut options = options.clone();
+    options.documents = true;
+
+    let tokens = Scanner::new(data, options.clone()).scan_tokens()?;
+    let mut documents = vec![];
+    let mut errors = vec![];
+    for (line, tokens) in split_documents(tokens) {
+        let mut parser = Parser::new(tokens, options.clone());
+        if let Some(filename) = filename {
+            parser = parser.in_file(filename);
+        }
+        match parser.parse() {
+            Ok(pair) => documents.push(Document::new(pair, line)),
+            Err(mut e) => errors.append(&mut e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(documents)
+    } else {
+        Err(errors)
+    }
+}
+
+fn in_file(errors: Vec<NcclError>, filename: &str) -> Vec<NcclError> {
+    errors.into_iter().map(|e| e.in_file(filename)).collect()
+}
+
+fn read_file(filename: &str) -> Result<String, Vec<NcclError>> {
+    if let Ok(mut file) = File::open(Path::new(filename)) {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|_| vec![NcclError::new(ErrorKind::Io, "IO error", 0).in_file(filename)])?;
+        encoding::decode(data).map_err(|err| vec![err.in_file(filename)])
+    } else {
+        Err(vec![NcclError::new(
+            ErrorKind::File,
+            "Could not find file.",
+            0,
+        )
+        .in_file(filename)])
+    }
 }
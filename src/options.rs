@@ -0,0 +1,378 @@
+use crate::interpolate::Variables;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// How the words `inf`, `infinity` and `nan` are interpreted when inferring
+/// the type of a value.
+pub enum SpecialFloats {
+    /// Keep them as strings, so a host named `inf` stays a string. This is
+    /// the default.
+    Strings,
+    /// Parse them as non-finite floats, ignoring case.
+    Floats,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// How consistent indentation has to be.
+pub enum IndentPolicy {
+    /// Each top-level key picks tabs or a number of spaces for everything
+    /// under it, and different keys may pick differently. This is the
+    /// default.
+    PerKey,
+    /// The first indented line picks tabs or a number of spaces for the
+    /// whole file.
+    FileWide,
+    /// A tab counts as this many spaces, so the two may be mixed. The width
+    /// of each level is still set per top-level key. The width must be at
+    /// least 1.
+    TabWidth(u8),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// The words that are inferred as booleans.
+///
+/// Examples:
+///
+/// ```
+/// use nccl::BoolVocabulary;
+/// let strict = BoolVocabulary::strict();
+/// assert_eq!(strict.parse("true"), Some(true));
+/// assert_eq!(strict.parse("yes"), None);
+///
+/// let lenient = BoolVocabulary::lenient();
+/// assert_eq!(lenient.parse("Enabled"), Some(true));
+/// assert_eq!(lenient.parse("OFF"), Some(false));
+/// ```
+pub struct BoolVocabulary {
+    true_words: Vec<String>,
+    false_words: Vec<String>,
+    ignore_case: bool,
+}
+
+impl BoolVocabulary {
+    /// Creates a case-sensitive vocabulary from lists of words.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let vocabulary = nccl::BoolVocabulary::new(&["ja"], &["nein"]);
+    /// assert_eq!(vocabulary.parse("nein"), Some(false));
+    /// ```
+    pub fn new(true_words: &[&str], false_words: &[&str]) -> Self {
+        BoolVocabulary {
+            true_words: true_words.iter().map(|w| w.to_string()).collect(),
+            false_words: false_words.iter().map(|w| w.to_string()).collect(),
+            ignore_case: false,
+        }
+    }
+
+    /// Only `true` and `false`, as Rust spells them. This is the default.
+    pub fn strict() -> Self {
+        BoolVocabulary::new(&["true"], &["false"])
+    }
+
+    /// `true`/`false`, `yes`/`no`, `y`/`n`, `on`/`off`, `enable`/`disable`
+    /// and `enabled`/`disabled`, ignoring case.
+    pub fn lenient() -> Self {
+        BoolVocabulary::new(
+            &["true", "yes", "y", "on", "enable", "enabled"],
+            &["false", "no", "n", "off", "disable", "disabled"],
+        )
+        .ignore_case(true)
+    }
+
+    /// Sets whether words are matched regardless of case.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Looks up a word in the vocabulary.
+    pub fn parse(&self, word: &str) -> Option<bool> {
+        let matches = |words: &[String]| {
+            words.iter().any(|w| {
+                if self.ignore_case {
+                    w.eq_ignore_ascii_case(word)
+                } else {
+                    w == word
+                }
+            })
+        };
+
+        if matches(&self.true_words) {
+            Some(true)
+        } else if matches(&self.false_words) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for BoolVocabulary {
+    fn default() -> Self {
+        BoolVocabulary::strict()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// Options that control how nccl source is parsed.
+///
+/// Examples:
+///
+/// ```
+/// use nccl::{ParseOptions, SpecialFloats};
+/// let options = ParseOptions::new().special_floats(SpecialFloats::Floats);
+/// let config = nccl::parse_string_with_options("x\n    inf", &options).unwrap();
+/// assert!(config["x"].value_as::<f64>().unwrap().is_infinite());
+/// ```
+pub struct ParseOptions {
+    pub(crate) special_floats: SpecialFloats,
+    pub(crate) bool_vocabulary: BoolVocabulary,
+    pub(crate) null_keywords: Vec<String>,
+    pub(crate) key_value_shorthand: bool,
+    pub(crate) inline_lists: bool,
+    pub(crate) legacy_comments: bool,
+    pub(crate) indent_policy: IndentPolicy,
+    pub(crate) max_include_depth: usize,
+    pub(crate) skip_hidden_files: bool,
+    pub(crate) interpolation: Option<Variables>,
+    pub(crate) documents: bool,
+    pub(crate) allow_trailing_text: bool,
+    pub(crate) allow_trailing_whitespace: bool,
+    pub(crate) allow_empty_keys: bool,
+}
+
+impl ParseOptions {
+    /// Creates the default set of options, which are the same as `lenient`.
+    pub fn new() -> Self {
+        ParseOptions {
+            special_floats: SpecialFloats::Strings,
+            bool_vocabulary: BoolVocabulary::strict(),
+            null_keywords: vec!["~".into()],
+            key_value_shorthand: false,
+            inline_lists: false,
+            legacy_comments: false,
+            indent_policy: IndentPolicy::PerKey,
+            max_include_depth: 16,
+            skip_hidden_files: true,
+            interpolation: None,
+            documents: false,
+            allow_trailing_text: true,
+            allow_trailing_whitespace: true,
+            allow_empty_keys: true,
+        }
+    }
+
+    /// Forgiving options that accept anything nccl has always accepted:
+    /// text after a closing quote is dropped, trailing whitespace is removed
+    /// and top-level keys may have no value.
+    pub fn lenient() -> Self {
+        ParseOptions::new()
+    }
+
+    /// Options that reject text after a closing quote, trailing whitespace
+    /// and top-level keys with no value. Useful for checking configs in CI.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::ParseOptions;
+    /// let source = "juice\n    \"bone hurting juice\" junk";
+    /// assert!(nccl::parse_string_with_options(source, &ParseOptions::lenient()).is_ok());
+    /// assert!(nccl::parse_string_with_options(source, &ParseOptions::strict()).is_err());
+    /// ```
+    pub fn strict() -> Self {
+        ParseOptions::new()
+            .allow_trailing_text(false)
+            .allow_trailing_whitespace(false)
+            .allow_empty_keys(false)
+    }
+
+    /// Sets how `inf` and `nan` are interpreted.
+    pub fn special_floats(mut self, policy: SpecialFloats) -> Self {
+        self.special_floats = policy;
+        self
+    }
+
+    /// Sets the words that are inferred as booleans.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::{BoolVocabulary, ParseOptions};
+    /// let options = ParseOptions::new().bool_vocabulary(BoolVocabulary::lenient());
+    /// let config = nccl::parse_string_with_options("tls\n    on", &options).unwrap();
+    /// assert!(config["tls"].value_as::<bool>().unwrap());
+    /// ```
+    pub fn bool_vocabulary(mut self, vocabulary: BoolVocabulary) -> Self {
+        self.bool_vocabulary = vocabulary;
+        self
+    }
+
+    /// Sets the bare words that are parsed as `Value::Null`. The default is
+    /// `~`. Quoting a keyword, as in `"~"`, always produces a string, and an
+    /// empty list disables null values entirely.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::{ParseOptions, Value};
+    /// let options = ParseOptions::new().null_keywords(&["null", "~"]);
+    /// let config = nccl::parse_string_with_options("proxy\n    null", &options).unwrap();
+    /// assert_eq!(config["proxy"].value_as::<Option<String>>().unwrap(), None);
+    /// ```
+    pub fn null_keywords(mut self, keywords: &[&str]) -> Self {
+        self.null_keywords = keywords.iter().map(|k| k.to_string()).collect();
+        self
+    }
+
+    /// Allows `key = value` on a single line as shorthand for the value
+    /// indented under the key. Dots in the key separate nested keys, so
+    /// `server.port = 8080` is the same as `8080` under `port` under
+    /// `server`. The `=` needs whitespace on both sides, so values such as
+    /// `http://x?a=b` or `abc==` are left alone. Off by default.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let options = nccl::ParseOptions::new().key_value_shorthand(true);
+    /// let config = nccl::parse_string_with_options("server.port = 8080", &options).unwrap();
+    /// assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+    /// ```
+    pub fn key_value_shorthand(mut self, enabled: bool) -> Self {
+        self.key_value_shorthand = enabled;
+        self
+    }
+
+    /// Allows a list of values in square brackets after a key, such as
+    /// `methods [GET, POST, PUT]`, as shorthand for each value indented under
+    /// the key. Items containing commas or brackets must be quoted. Off by
+    /// default.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let options = nccl::ParseOptions::new().inline_lists(true);
+    /// let config = nccl::parse_string_with_options("methods [GET, \"A, B\"]", &options).unwrap();
+    /// assert_eq!(config["methods"].keys_as::<String>().unwrap(), vec!["GET", "A, B"]);
+    /// ```
+    pub fn inline_lists(mut self, enabled: bool) -> Self {
+        self.inline_lists = enabled;
+        self
+    }
+
+    /// Goes back to treating every `#` in a bare word as the start of a
+    /// comment. By default a comment only starts at a `#` at the beginning of
+    /// a line or after whitespace, so values like `example.com/docs#install`
+    /// are kept whole. Either way, `\#` is a literal `#`.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let source = "docs\n    example.com/docs#install # a comment";
+    /// let config = nccl::parse_string(source).unwrap();
+    /// assert_eq!(config["docs"].value().unwrap(), "example.com/docs#install");
+    ///
+    /// let options = nccl::ParseOptions::new().legacy_comments(true);
+    /// let config = nccl::parse_string_with_options(source, &options).unwrap();
+    /// assert_eq!(config["docs"].value().unwrap(), "example.com/docs");
+    /// ```
+    pub fn legacy_comments(mut self, enabled: bool) -> Self {
+        self.legacy_comments = enabled;
+        self
+    }
+
+    /// Sets how consistent indentation has to be.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::{IndentPolicy, ParseOptions};
+    /// let source = "a\n    b\n        c\nd\n\te";
+    /// assert!(nccl::parse_string(source).is_ok());
+    ///
+    /// let options = ParseOptions::new().indent_policy(IndentPolicy::FileWide);
+    /// assert!(nccl::parse_string_with_options(source, &options).is_err());
+    ///
+    /// let options = ParseOptions::new().indent_policy(IndentPolicy::TabWidth(4));
+    /// let config = nccl::parse_string_with_options("a\n    b\n\t    c", &options).unwrap();
+    /// assert_eq!(config["a"]["b"].value().unwrap(), "c");
+    /// ```
+    pub fn indent_policy(mut self, policy: IndentPolicy) -> Self {
+        self.indent_policy = policy;
+        self
+    }
+
+    /// Sets how deeply `@include` and `@extends` directives may nest before
+    /// parsing gives up. The default is 16.
+    pub fn max_include_depth(mut self, depth: usize) -> Self {
+        self.max_include_depth = depth;
+        self
+    }
+
+    /// Sets whether `parse_dir` skips dotfiles and the backup files editors
+    /// leave behind, such as `app.nccl~`, `app.nccl.bak`, `app.nccl.swp` and
+    /// `#app.nccl#`. On by default.
+    pub fn skip_hidden_files(mut self, skip: bool) -> Self {
+        self.skip_hidden_files = skip;
+        self
+    }
+
+    /// Turns on `${VAR}` interpolation in keys and values, looking variables
+    /// up in `vars`. `${VAR:-default}` falls back to a default and
+    /// `${VAR:?message}` is an error with a message when the variable is
+    /// unset or empty, and `$$` is a literal `$`. Defaults and messages
+    /// can't contain another `${...}`. Raw strings are left alone. Off by
+    /// default.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::{ParseOptions, Variables};
+    /// let vars = Variables::Fixed(vec![("USER".into(), "ferris".into())]);
+    /// let options = ParseOptions::new().interpolation(vars);
+    /// let source = "data\n    /home/${USER}/data\npassword\n    ${DB_PASSWORD:-changeme}";
+    /// let config = nccl::parse_string_with_options(source, &options).unwrap();
+    /// assert_eq!(config["data"].value().unwrap(), "/home/ferris/data");
+    /// assert_eq!(config["password"].value().unwrap(), "changeme");
+    /// ```
+    pub fn interpolation(mut self, vars: Variables) -> Self {
+        self.interpolation = Some(vars);
+        self
+    }
+
+    /// Sets whether text after a closing quote, raw string or inline list is
+    /// ignored rather than an error. Comments are always allowed.
+    pub fn allow_trailing_text(mut self, allow: bool) -> Self {
+        self.allow_trailing_text = allow;
+        self
+    }
+
+    /// Sets whether whitespace at the end of a line is removed rather than
+    /// an error.
+    pub fn allow_trailing_whitespace(mut self, allow: bool) -> Self {
+        self.allow_trailing_whitespace = allow;
+        self
+    }
+
+    /// Sets whether a top-level key may have no value. Use `~` for a value
+    /// that is deliberately unset.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let options = nccl::ParseOptions::new().allow_empty_keys(false);
+    /// assert!(nccl::parse_string_with_options("forgotten\n", &options).is_err());
+    /// assert!(nccl::parse_string_with_options("unset\n    ~\n", &options).is_ok());
+    /// ```
+    pub fn allow_empty_keys(mut self, allow: bool) -> Self {
+        self.allow_empty_keys = allow;
+        self
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::new()
+    }
+}
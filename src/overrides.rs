@@ -0,0 +1,175 @@
+use crate::error::{ErrorKind, NcclError};
+use crate::pair::Pair;
+use crate::value::{parse_into_value, Value};
+
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operation {
+    Set(Value),
+    Append(Value),
+    Remove(Option<Value>),
+}
+
+/// A change to a configuration given on the command line, such as
+/// `server.port=8080`. Dots separate nested keys, and values are inferred
+/// like values in a file.
+///
+/// * `path=value` replaces the values of `path` with `value`
+/// * `path+=value` adds `value` to the values of `path`
+/// * `path-=value` removes `value` from the values of `path`
+/// * `path-=` removes `path` and everything under it
+///
+/// Examples:
+///
+/// ```
+/// use nccl::Override;
+/// let mut config = nccl::parse_file("examples/config.nccl").unwrap();
+/// let port: Override = "server.port=8080".parse().unwrap();
+/// port.apply(&mut config).unwrap();
+/// assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Override {
+    expression: String,
+    path: Vec<Value>,
+    operation: Operation,
+}
+
+impl Override {
+    /// Applies the override to a Pair.
+    pub fn apply(&self, pair: &mut Pair) -> Result<(), NcclError> {
+        let (key, parent) = self.path.split_last().unwrap();
+
+        match &self.operation {
+            Operation::Set(value) => {
+                let target = pair.traverse_path(&self.path);
+                for old in target.keys_as::<Value>().unwrap() {
+                    target.remove(old);
+                }
+                target.add(value.clone());
+            }
+
+            Operation::Append(value) => {
+                pair.traverse_path(&self.path).add(value.clone());
+            }
+
+            Operation::Remove(value) => {
+                let removed = pair.has_path(self.path.clone())
+                    && match value {
+                        Some(value) => pair.traverse_path(&self.path).remove(value),
+                        None => pair.traverse_path(parent).remove(key),
+                    };
+
+                if !removed {
+                    let path = self.display_path();
+                    return Err(self.error(&match value {
+                        Some(value) => format!("{} has no value {}", path, value),
+                        None => format!("{} isn't set", path),
+                    }));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn display_path(&self) -> String {
+        let path: Vec<String> = self.path.iter().map(|v| v.to_string()).collect();
+        path.join("/")
+    }
+
+    fn error(&self, reason: &str) -> NcclError {
+        NcclError::new(
+            ErrorKind::Override,
+            &format!("Override `{}` failed: {}", self.expression, reason),
+            0,
+        )
+    }
+}
+
+impl FromStr for Override {
+    type Err = NcclError;
+
+    fn from_str(expression: &str) -> Result<Self, NcclError> {
+        let error = |reason: &str| {
+            NcclError::new(
+                ErrorKind::Override,
+                &format!("Override `{}` failed: {}", expression, reason),
+                0,
+            )
+        };
+
+        let equals = expression
+            .find('=')
+            .ok_or_else(|| error("expected `=`, `+=` or `-=` after the key"))?;
+        let (path, op) = match &expression[..equals] {
+            path if path.ends_with('+') => (&path[..path.len() - 1], "+="),
+            path if path.ends_with('-') => (&path[..path.len() - 1], "-="),
+            path => (path, "="),
+        };
+        let value = expression[equals + 1..].trim();
+
+        let keys: Vec<&str> = path.trim().split('.').map(str::trim).collect();
+        if keys.iter().any(|key| key.is_empty()) {
+            return Err(error(
+                "expected a key such as `server.port` before the operator",
+            ));
+        }
+        let path = keys
+            .iter()
+            .map(|&key| parse_into_value(key.into()))
+            .collect();
+
+        let value = parse_into_value(value.into());
+        let operation = match (op, value) {
+            ("-=", Value::String(ref s)) if s.is_empty() => Operation::Remove(None),
+            ("-=", value) => Operation::Remove(Some(value)),
+            (_, Value::String(ref s)) if s.is_empty() => {
+                return Err(error(&format!("expected a value after `{}`", op)))
+            }
+            ("+=", value) => Operation::Append(value),
+            (_, value) => Operation::Set(value),
+        };
+
+        Ok(Override {
+            expression: expression.into(),
+            path,
+            operation,
+        })
+    }
+}
+
+/// Parses and applies overrides in order, such as the values of repeated
+/// `--set` flags. Every override that can't be parsed or applied is
+/// reported, and the others are still applied.
+///
+/// Examples:
+///
+/// ```
+/// let mut config = nccl::parse_file("examples/config.nccl").unwrap();
+/// nccl::apply_overrides(
+///     &mut config,
+///     &["server.port=8080", "server.domain+=api.example.com", "server.root-="],
+/// )
+/// .unwrap();
+/// assert_eq!(config["server"]["domain"].keys_as::<String>().unwrap().len(), 3);
+/// assert!(!config["server"].has_key("root"));
+///
+/// let errors = nccl::apply_overrides(&mut config, &["server.port-=80"]).unwrap_err();
+/// assert!(format!("{}", errors[0]).contains("server/port has no value 80"));
+/// ```
+pub fn apply_overrides(pair: &mut Pair, expressions: &[&str]) -> Result<(), Vec<NcclError>> {
+    let mut errors = vec![];
+    for expression in expressions {
+        if let Err(e) = expression.parse::<Override>().and_then(|o| o.apply(pair)) {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
@@ -1,9 +1,32 @@
 use crate::error::{ErrorKind, NcclError};
+use crate::options::BoolVocabulary;
 use crate::value::Value;
 
 use std::convert::TryInto;
 use std::ops::{Index, IndexMut};
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// How `Pair::merge` combines two trees. Keys with more keys under them are
+/// always merged key by key; the strategies differ in what happens to a key
+/// that holds values, like `port` holding `80` and `443`.
+pub enum MergeStrategy {
+    /// Merges key by key all the way down, so the result has every path from
+    /// both trees and a value on both sides appears once.
+    DeepMerge,
+    /// Values from the other tree replace the values of the same key.
+    ReplaceLeaves,
+    /// Values from the other tree are added after the values of the same
+    /// key, even if they are already there. This is what `parse_file_with`
+    /// does.
+    AppendLists,
+    /// Values from the other tree are added after the values of the same
+    /// key, and each value is kept only once.
+    UnionLists,
+    /// Merges like `DeepMerge`, but a key with different values on each side,
+    /// or with values on one side and more keys on the other, is an error.
+    ErrorOnConflict,
+}
+
 /// Struct that contains configuration information.
 ///
 /// Examples:
@@ -32,6 +55,10 @@ impl Pair {
         }
     }
 
+    pub(crate) fn is_leaf(&self) -> bool {
+        self.value.is_empty()
+    }
+
     /// Adds a value to a Pair.
     ///
     /// Examples:
@@ -53,6 +80,30 @@ impl Pair {
         }
     }
 
+    // adds everything in `tree` under `path`, as if its lines had been
+    // written there
+    pub(crate) fn add_tree(&mut self, path: &mut Vec<Value>, tree: &Pair) {
+        for child in &tree.value {
+            path.push(child.key.clone());
+            self.add_slice(path);
+            self.add_tree(path, child);
+            path.pop();
+        }
+    }
+
+    // every path in the tree, parents before their children
+    pub(crate) fn paths(&self) -> Vec<Vec<Value>> {
+        let mut paths = vec![];
+        for child in &self.value {
+            paths.push(vec![child.key.clone()]);
+            for mut path in child.paths() {
+                path.insert(0, child.key.clone());
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
     /// Adds a Pair to a Pair.
     pub fn add_pair(&mut self, pair: Pair) {
         if !self.has_key(&pair.key) {
@@ -62,6 +113,167 @@ impl Pair {
         }
     }
 
+    /// Removes a key and everything under it from a Pair, returning whether
+    /// the key was there. A value that appears more than once is removed
+    /// every time.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let mut p = nccl::parse_file("examples/inherit.nccl").unwrap();
+    /// assert!(p["sandwich"]["meat"].remove("ham"));
+    /// assert!(!p["sandwich"]["meat"].has_key("ham"));
+    /// assert!(!p["sandwich"]["meat"].remove("ham"));
+    /// ```
+    pub fn remove<T>(&mut self, key: T) -> bool
+    where
+        Value: From<T>,
+    {
+        let k = key.into();
+        let len = self.value.len();
+        self.value.retain(|item| item.key != k);
+        self.value.len() != len
+    }
+
+    /// Merges another Pair into this one using a merge strategy.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::MergeStrategy;
+    /// let mut config = nccl::parse_string("server\n    port\n        80").unwrap();
+    /// let local = nccl::parse_string("server\n    port\n        8080").unwrap();
+    /// config.merge(local, MergeStrategy::ReplaceLeaves).unwrap();
+    /// assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+    /// ```
+    pub fn merge(&mut self, other: Pair, strategy: MergeStrategy) -> Result<(), NcclError> {
+        self.merge_with(other, strategy, |_, _, _| Ok(()))
+    }
+
+    /// Merges another Pair into this one, calling `on_conflict` with the
+    /// path of each key that has different values on each side, or values on
+    /// one side and more keys on the other, followed by this side and the
+    /// other side. Returning an error stops the merge.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::MergeStrategy;
+    /// let mut config = nccl::parse_file("examples/inherit.nccl").unwrap();
+    /// let user = nccl::parse_file("examples/inherit2.nccl").unwrap();
+    /// let mut conflicts = vec![];
+    /// config
+    ///     .merge_with(user, MergeStrategy::ReplaceLeaves, |path, _, _| {
+    ///         conflicts.push(path.to_vec());
+    ///         Ok(())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(conflicts.len(), 4);
+    /// assert_eq!(config["sandwich"]["meat"].value().unwrap(), "turkey");
+    /// ```
+    pub fn merge_with<F>(
+        &mut self,
+        other: Pair,
+        strategy: MergeStrategy,
+        mut on_conflict: F,
+    ) -> Result<(), NcclError>
+    where
+        F: FnMut(&[Value], &Pair, &Pair) -> Result<(), NcclError>,
+    {
+        self.merge_rec(&mut vec![], other, strategy, &mut on_conflict)
+    }
+
+    fn merge_rec<F>(
+        &mut self,
+        path: &mut Vec<Value>,
+        other: Pair,
+        strategy: MergeStrategy,
+        on_conflict: &mut F,
+    ) -> Result<(), NcclError>
+    where
+        F: FnMut(&[Value], &Pair, &Pair) -> Result<(), NcclError>,
+    {
+        let has_values = |p: &Pair| !p.value.is_empty() && p.value.iter().all(Pair::is_leaf);
+        let has_keys = |p: &Pair| p.value.iter().any(|v| !v.is_leaf());
+
+        // a key holding values on one side and more keys on the other can't
+        // be merged key by key. keys without values at the top level are
+        // just keys, so the top level is always merged key by key
+        let reshaped = !path.is_empty()
+            && ((has_values(self) && has_keys(&other)) || (has_keys(self) && has_values(&other)));
+
+        let both_values = !path.is_empty() && has_values(self) && has_values(&other);
+        if both_values || reshaped {
+            let differs = reshaped
+                || self.value.iter().any(|v| !other.has_key(&v.key))
+                || other.value.iter().any(|v| !self.has_key(&v.key));
+            if differs {
+                on_conflict(path, self, &other)?;
+
+                if strategy == MergeStrategy::ErrorOnConflict {
+                    let path: Vec<String> = path.iter().map(|v| v.to_string()).collect();
+                    let values = |p: &Pair| {
+                        p.keys()
+                            .iter()
+                            .map(|v| v.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
+                    return Err(NcclError::new(
+                        ErrorKind::Conflict,
+                        &format!(
+                            "Conflicting values for {}: {} and {}",
+                            path.join("/"),
+                            values(self),
+                            values(&other)
+                        ),
+                        0,
+                    ));
+                }
+            }
+        }
+
+        if reshaped && strategy == MergeStrategy::ReplaceLeaves {
+            self.value = other.value;
+            return Ok(());
+        }
+
+        if both_values {
+            match strategy {
+                MergeStrategy::ReplaceLeaves => {
+                    self.value = other.value;
+                    return Ok(());
+                }
+                MergeStrategy::AppendLists => {
+                    self.value.extend(other.value);
+                    return Ok(());
+                }
+                MergeStrategy::UnionLists => {
+                    let mut values: Vec<Pair> = vec![];
+                    for value in self.value.drain(..).chain(other.value) {
+                        if !values.iter().any(|v| v.key == value.key) {
+                            values.push(value);
+                        }
+                    }
+                    self.value = values;
+                    return Ok(());
+                }
+                MergeStrategy::DeepMerge | MergeStrategy::ErrorOnConflict => {}
+            }
+        }
+
+        for child in other.value {
+            path.push(child.key.clone());
+            match self.value.iter_mut().find(|v| v.key == child.key) {
+                Some(existing) => existing.merge_rec(path, child, strategy, on_conflict)?,
+                None => self.value.push(child),
+            }
+            path.pop();
+        }
+
+        Ok(())
+    }
+
     /// Test if a pair has a key.
     ///
     /// Examples:
@@ -238,6 +450,52 @@ impl Pair {
         }
     }
 
+    /// Gets the value of a key as a bool, also accepting words such as `yes`,
+    /// `off` or `Enabled` (see `BoolVocabulary::lenient`) and the integers
+    /// `1` and `0`.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// let p = nccl::parse_string("a\n    yes\nb\n    0").unwrap();
+    /// assert!(p["a"].value_as_bool_lenient().unwrap());
+    /// assert!(!p["b"].value_as_bool_lenient().unwrap());
+    /// ```
+    pub fn value_as_bool_lenient(&self) -> Result<bool, NcclError> {
+        self.value_as_bool_with(&BoolVocabulary::lenient())
+    }
+
+    /// Gets the value of a key as a bool, accepting the words of a
+    /// vocabulary and the integers `1` and `0`.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// use nccl::BoolVocabulary;
+    /// let german = BoolVocabulary::new(&["ja"], &["nein"]);
+    /// let p = nccl::parse_string("a\n    ja\nb\n    yes").unwrap();
+    /// assert!(p["a"].value_as_bool_with(&german).unwrap());
+    /// assert!(p["b"].value_as_bool_with(&german).is_err());
+    /// ```
+    pub fn value_as_bool_with(&self, vocabulary: &BoolVocabulary) -> Result<bool, NcclError> {
+        let b = match self.value_raw() {
+            Some(Value::Bool(b)) => Some(b),
+            Some(Value::Integer(1)) => Some(true),
+            Some(Value::Integer(0)) => Some(false),
+            Some(Value::String(s)) => vocabulary.parse(&s),
+            Some(_) => None,
+            None => {
+                return Err(NcclError::new(
+                    ErrorKind::MultipleValues,
+                    "Could not convert value: multiple values. Use keys() or keys_as()",
+                    0,
+                ))
+            }
+        };
+
+        b.ok_or_else(|| NcclError::new(ErrorKind::Into, "Could not convert to bool", 0))
+    }
+
     /// Gets the value of a key as a specified type or a default value.
     pub fn value_as_or<T>(&self, or: T) -> T
     where
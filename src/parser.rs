@@ -1,111 +1,212 @@
 use crate::error::{ErrorKind, NcclError};
+use crate::interpolate::interpolate;
+use crate::options::ParseOptions;
 use crate::pair::Pair;
+use crate::scanner::Scanner;
 use crate::token::{Token, TokenKind};
-use crate::value::{parse_into_value, Value};
+use crate::value::{parse_into_value_with, Value};
+
+use std::path::{Path, PathBuf};
+
+/// Splits the tokens of a multi-document source at each `---`, pairing each
+/// document with the line it starts on. An empty document before the first
+/// separator or after the last one is dropped.
+pub fn split_documents(tokens: Vec<Token>) -> Vec<(u64, Vec<Token>)> {
+    let mut documents = vec![(1, vec![])];
+    for token in tokens {
+        match token.kind {
+            TokenKind::Separator => {
+                documents.push((token.line + 1, vec![]));
+            }
+            TokenKind::Eof => {
+                for (_, document) in &mut documents {
+                    document.push(Token::new(TokenKind::Eof, "".into(), token.line));
+                }
+            }
+            _ => documents.last_mut().unwrap().1.push(token),
+        }
+    }
+
+    let is_empty = |tokens: &Vec<Token>| {
+        tokens.iter().all(|token| {
+            matches!(
+                token.kind,
+                TokenKind::Indent | TokenKind::Newline | TokenKind::Eof
+            )
+        })
+    };
+    if documents.len() > 1 && is_empty(&documents[documents.len() - 1].1) {
+        documents.pop();
+    }
+    if documents.len() > 1 && is_empty(&documents[0].1) {
+        documents.remove(0);
+    }
+
+    documents
+}
 
 #[derive(Debug)]
 pub struct Parser {
     current: usize,
-    path: Vec<Value>,
-    indent: usize,
     tokens: Vec<Token>,
     pair: Pair,
-    line: u64,
+    options: ParseOptions,
+    // the file being parsed and the files that included it, outermost first
+    files: Vec<PathBuf>,
+    depth: usize,
+    // where each @ref is, what it refers to and its line
+    references: Vec<(Vec<Value>, Vec<Value>, u64)>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser {
-            current: 0,
-            path: vec![],
-            indent: 0,
-            tokens,
-            pair: Pair::new("__top_level__"),
-            line: 1,
-        }
+    pub fn new(tokens: Vec<Token>, options: ParseOptions) -> Self {
+        Parser::new_with(tokens, Pair::new("__top_level__"), options)
     }
 
-    pub fn new_with(tokens: Vec<Token>, pair: Pair) -> Self {
+    pub fn new_with(tokens: Vec<Token>, pair: Pair, options: ParseOptions) -> Self {
         Parser {
             current: 0,
-            path: vec![],
-            indent: 0,
             tokens,
             pair,
-            line: 1,
+            options,
+            files: vec![],
+            depth: 0,
+            references: vec![],
         }
     }
 
+    /// Sets the file being parsed, which included files are found relative
+    /// to.
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.files = vec![PathBuf::from(file)];
+        self
+    }
+
     pub fn parse(mut self) -> Result<Pair, Vec<NcclError>> {
         let mut errors = vec![];
-        let mut prev_indent = 0;
-        let mut i = 0;
-
-        while i < self.tokens.len() {
-            match self.tokens[i].kind {
-                TokenKind::Value => {
-                    // add to path respective of self.index
-                    if self.indent <= self.path.len() {
-                        let mut new = self.path[0..self.indent].to_owned();
-                        new.push(parse_into_value(self.tokens[i].lexeme.clone()));
-                        self.path = new;
-                    } else {
-                        self.path
-                            .push(parse_into_value(self.tokens[i].lexeme.clone()));
-                    }
 
-                    self.pair.add_slice(&self.path);
+        // the values of the most recent line at each level of indentation
+        let mut levels: Vec<Vec<Value>> = vec![];
+        let mut top_level = vec![];
+        let mut started = false;
+
+        while !self.is_at_end() {
+            let line = self.peek().line;
 
-                    if i + 2 < self.tokens.len() && self.tokens[i + 2].kind == TokenKind::Value {
-                        self.path.clear();
-                        self.indent = 0;
+            let mut indent = 0;
+            while self.check(TokenKind::Indent) {
+                indent += 1;
+                self.advance();
+            }
+
+            let directive = if self.check(TokenKind::Directive) {
+                Some(self.advance())
+            } else {
+                None
+            };
+
+            // each value after the first on a line is a child of the one
+            // before it
+            let mut values = vec![];
+            while self.check_value() {
+                let token = self.advance();
+                match self.value(&token) {
+                    Ok(value) => values.push(value),
+                    Err(e) => errors.push(e),
+                }
+            }
+
+            // items of an inline list are children of the last value
+            let mut items = vec![];
+            if self.check(TokenKind::ListStart) {
+                self.advance();
+                while self.check_value() {
+                    let token = self.advance();
+                    match self.value(&token) {
+                        Ok(value) => items.push(value),
+                        Err(e) => errors.push(e),
                     }
                 }
+                if self.check(TokenKind::ListEnd) {
+                    self.advance();
+                }
+            }
 
-                TokenKind::Indent => {
-                    // set new self.index
-                    let mut indent = 0;
+            if self.check(TokenKind::Newline) {
+                self.advance();
+            }
 
-                    while self.tokens[i].kind == TokenKind::Indent {
-                        indent += 1;
-                        i += 1;
-                    }
+            // blank lines and comments don't affect indentation
+            if directive.is_none() && values.is_empty() && items.is_empty() {
+                continue;
+            }
 
-                    i -= 1;
-
-                    if prev_indent > indent {
-                        if prev_indent - indent == 1 || prev_indent - indent == 0 {
-                            self.indent = indent;
-                        } else {
-                            errors.push(NcclError::new(
-                                ErrorKind::Indentation,
-                                "Incorrect level of indentation found",
-                                self.line,
-                            ));
-                            self.indent = prev_indent;
-                        }
-                    } else if indent - prev_indent == 1 || indent - prev_indent == 0 {
-                        self.indent = indent;
-                    } else {
-                        errors.push(NcclError::new(
-                            ErrorKind::Indentation,
-                            "Incorrect level of indentation found",
-                            self.line,
-                        ));
-                        self.indent = prev_indent;
-                    }
+            if indent > levels.len() {
+                errors.push(NcclError::new(
+                    ErrorKind::Indentation,
+                    "Incorrect level of indentation found",
+                    line,
+                ));
+                indent = levels.len();
+            }
+
+            if let Some(directive) = directive {
+                if directive.lexeme == "extends" && (started || indent > 0) {
+                    errors.push(NcclError::new(
+                        ErrorKind::Include,
+                        "@extends must come before everything else in the file",
+                        line,
+                    ));
+                    continue;
                 }
+                started = true;
 
-                TokenKind::Newline => {
-                    // reset self.index
-                    prev_indent = self.indent;
-                    self.indent = 0;
-                    self.line += 1;
+                levels.truncate(indent);
+                let mut path = levels.concat();
+                match self.directive(&directive, &values, &mut path) {
+                    // a replaced key has its new value indented under it
+                    Ok(()) if directive.lexeme == "replace" => levels.push(values),
+                    Ok(()) => {}
+                    Err(mut e) => errors.append(&mut e),
                 }
+                continue;
+            }
+
+            started = true;
+            if indent == 0 && !values.is_empty() {
+                top_level.push((values[0].clone(), line));
+            }
+
+            levels.truncate(indent);
+            let mut path = levels.concat();
+            for value in &values {
+                path.push(value.clone());
+                self.pair.add_slice(&path);
+            }
+            for item in items {
+                path.push(item);
+                self.pair.add_slice(&path);
+                path.pop();
+            }
 
-                TokenKind::EOF => break,
+            if !values.is_empty() {
+                levels.push(values);
+            }
+        }
+
+        errors.append(&mut self.resolve_references());
+
+        if !self.options.allow_empty_keys {
+            // keys removed by @unset are no longer in the pair
+            for (key, line) in top_level {
+                if self.pair.has_key(&key) && self.pair[&key].is_leaf() {
+                    errors.push(NcclError::new(
+                        ErrorKind::Parse,
+                        &format!("Key has no value: {}", key),
+                        line,
+                    ));
+                }
             }
-            i += 1;
         }
 
         if errors.is_empty() {
@@ -114,4 +215,268 @@ impl Parser {
             Err(errors)
         }
     }
+
+    // @include and @extends take a file name, @unset and @replace take a key
+    // under the current one, and @ref takes a path from the top level like
+    // `server/domain`. only @replace can have children
+    fn directive(
+        &mut self,
+        directive: &Token,
+        args: &[Value],
+        path: &mut Vec<Value>,
+    ) -> Result<(), Vec<NcclError>> {
+        let error =
+            |message: String| vec![NcclError::new(ErrorKind::Parse, &message, directive.line)];
+
+        let arg = match args {
+            [arg] => arg,
+            _ if directive.lexeme == "include" || directive.lexeme == "extends" => {
+                return Err(error(format!(
+                    "Expected a file name after @{}",
+                    directive.lexeme
+                )))
+            }
+            _ => return Err(error(format!("Expected a key after @{}", directive.lexeme))),
+        };
+
+        match directive.lexeme.as_str() {
+            "include" | "extends" => {
+                self.load(&directive.lexeme, &arg.to_string(), directive.line, path)
+            }
+
+            "unset" => {
+                if self.pair.has_path(path.clone()) && self.pair.traverse_path(path).remove(arg) {
+                    Ok(())
+                } else {
+                    path.push(arg.clone());
+                    Err(error(format!(
+                        "Can't unset {}, it isn't set",
+                        display_path(path)
+                    )))
+                }
+            }
+
+            "replace" => {
+                self.pair.traverse_path(path).remove(arg);
+                path.push(arg.clone());
+                self.pair.add_slice(path);
+                Ok(())
+            }
+
+            "ref" => {
+                let target: Vec<Value> = arg
+                    .to_string()
+                    .split('/')
+                    .map(|key| parse_into_value_with(key.trim().into(), &self.options))
+                    .collect();
+                if target.iter().any(|key| key.to_string().is_empty()) {
+                    return Err(error(format!(
+                        "Expected a path like server/domain, found {}",
+                        arg
+                    )));
+                }
+
+                self.references.push((path.clone(), target, directive.line));
+                Ok(())
+            }
+
+            _ => unreachable!("unknown directive {}", directive.lexeme),
+        }
+    }
+
+    // copies what each @ref refers to, once everything it depends on has
+    // been copied
+    fn resolve_references(&mut self) -> Vec<NcclError> {
+        let mut done = vec![false; self.references.len()];
+        let mut errors = vec![];
+        for i in 0..self.references.len() {
+            let mut stack = vec![];
+            if let Err(e) = self.resolve_reference(i, &mut done, &mut stack) {
+                errors.push(e);
+                // don't report the same problem from every @ref involved
+                for j in stack {
+                    done[j] = true;
+                }
+            }
+        }
+        errors
+    }
+
+    fn resolve_reference(
+        &mut self,
+        i: usize,
+        done: &mut Vec<bool>,
+        stack: &mut Vec<usize>,
+    ) -> Result<(), NcclError> {
+        let (path, target, line) = self.references[i].clone();
+        if done[i] {
+            return Ok(());
+        }
+
+        if let Some(start) = stack.iter().position(|&j| j == i) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .chain(Some(&i))
+                .map(|&j| display_path(&self.references[j].0))
+                .collect();
+            return Err(NcclError::new(
+                ErrorKind::Reference,
+                &format!("Reference cycle: {}", cycle.join(" -> ")),
+                line,
+            ));
+        }
+
+        // another @ref has to be copied first if it adds to what this one
+        // refers to, or if what this one refers to comes from it
+        stack.push(i);
+        for j in 0..self.references.len() {
+            let other = &self.references[j].0;
+            if other.starts_with(&target) || (j != i && target.starts_with(other)) {
+                self.resolve_reference(j, done, stack)?;
+            }
+        }
+        stack.pop();
+
+        if !self.pair.has_path(target.clone()) {
+            return Err(NcclError::new(
+                ErrorKind::Reference,
+                &format!(
+                    "{} refers to {}, which isn't set",
+                    display_path(&path),
+                    display_path(&target)
+                ),
+                line,
+            ));
+        }
+
+        let tree = self.pair.traverse_path(&target).clone();
+        self.pair.add_tree(&mut path.clone(), &tree);
+        done[i] = true;
+        Ok(())
+    }
+
+    // parses another file relative to this one and adds its contents under
+    // the path of the directive. a parent named by @extends is loaded the
+    // same way before anything else in the file
+    fn load(
+        &mut self,
+        directive: &str,
+        name: &str,
+        line: u64,
+        path: &mut Vec<Value>,
+    ) -> Result<(), Vec<NcclError>> {
+        let error = |message: String| vec![NcclError::new(ErrorKind::Include, &message, line)];
+
+        let file = match self.files.last() {
+            Some(including) => including.parent().unwrap_or(Path::new("")).join(name),
+            None => PathBuf::from(name),
+        };
+        let canonical = file.canonicalize().map_err(|_| {
+            error(format!(
+                "Could not find {} named by @{}",
+                file.display(),
+                directive
+            ))
+        })?;
+
+        if let Some(i) = self
+            .files
+            .iter()
+            .position(|f| f.canonicalize().ok().as_ref() == Some(&canonical))
+        {
+            let cycle: Vec<String> = self.files[i..]
+                .iter()
+                .chain(Some(&file))
+                .map(|f| f.display().to_string())
+                .collect();
+            let kind = if directive == "extends" {
+                "Inheritance"
+            } else {
+                "Include"
+            };
+            return Err(error(format!("{} cycle: {}", kind, cycle.join(" -> "))));
+        }
+
+        if self.depth >= self.options.max_include_depth {
+            return Err(error(format!(
+                "@{} is nested more than {} deep at {}",
+                directive,
+                self.options.max_include_depth,
+                file.display()
+            )));
+        }
+
+        let filename = file.to_string_lossy().to_string();
+        let mut files = self.files.clone();
+        files.push(file);
+
+        let tree = crate::read_file(&filename)
+            .and_then(|data| Scanner::new(data, self.options.clone()).scan_tokens())
+            .and_then(|tokens| {
+                let mut parser = Parser::new(tokens, self.options.clone());
+                parser.files = files;
+                parser.depth = self.depth + 1;
+                parser.parse()
+            })
+            .map_err(|errors| {
+                errors
+                    .into_iter()
+                    .map(|e| {
+                        let e = e.in_file(&filename);
+                        match self.files.last() {
+                            Some(including) => e.included_from(&including.to_string_lossy(), line),
+                            None => e,
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })?;
+
+        self.pair.add_tree(path, &tree);
+        Ok(())
+    }
+
+    fn value(&self, token: &Token) -> Result<Value, NcclError> {
+        // raw strings are never interpolated
+        let text = match &self.options.interpolation {
+            Some(vars) if token.kind != TokenKind::Raw => interpolate(&token.lexeme, vars)
+                .map_err(|reason| NcclError::new(ErrorKind::Interpolation, &reason, token.line))?,
+            _ => token.lexeme.clone(),
+        };
+
+        Ok(match parse_into_value_with(text.clone(), &self.options) {
+            // quoting a null keyword makes it a plain string
+            Value::Null if token.kind != TokenKind::Value => Value::String(text),
+            value => value,
+        })
+    }
+
+    fn check_value(&self) -> bool {
+        self.check(TokenKind::Value) || self.check(TokenKind::Quoted) || self.check(TokenKind::Raw)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.tokens.len() || self.check(TokenKind::Eof)
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.current < self.tokens.len() && self.tokens[self.current].kind == kind
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn advance(&mut self) -> Token {
+        self.current += 1;
+        self.tokens[self.current - 1].clone()
+    }
+}
+
+fn display_path(path: &[Value]) -> String {
+    if path.is_empty() {
+        "the top level".into()
+    } else {
+        let path: Vec<String> = path.iter().map(|v| v.to_string()).collect();
+        path.join("/")
+    }
 }
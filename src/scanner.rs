@@ -1,11 +1,27 @@
 use crate::error::{ErrorKind, NcclError};
+use crate::options::{IndentPolicy, ParseOptions};
 use crate::token::{Token, TokenKind};
 
-// ranked worst to best
+use std::fmt;
+
+const DIRECTIVES: &[&str] = &["include", "extends", "unset", "replace", "ref"];
+
+// the indentation style of the current top-level key, or the whole file
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Indent {
     Neither,
     Tabs,
-    Spaces(u8),
+    Spaces(usize),
+}
+
+impl fmt::Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Indent::Neither => write!(f, "nothing"),
+            Indent::Tabs => write!(f, "tabs"),
+            Indent::Spaces(n) => write!(f, "{} spaces per level", n),
+        }
+    }
 }
 
 pub struct Scanner {
@@ -15,24 +31,65 @@ pub struct Scanner {
     current: usize,
     line: u64,
     indent: Indent,
+    // line and top-level key where the indentation style was set
+    established: (u64, String),
+    key: (u64, String),
+    options: ParseOptions,
 }
 
 impl Scanner {
-    pub fn new(source: String) -> Self {
+    pub fn new(source: String, options: ParseOptions) -> Self {
         Scanner {
-            source: source.into_bytes(),
+            source: source
+                .strip_prefix('\u{feff}')
+                .map_or(source.as_bytes(), str::as_bytes)
+                .to_vec(),
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
             indent: Indent::Neither,
+            established: (0, String::new()),
+            key: (0, String::new()),
+            options,
         }
     }
 
     pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<NcclError>> {
         let mut err: Vec<NcclError> = vec![];
 
+        // every level of indentation must be at least one space wide
+        if self.options.indent_policy == IndentPolicy::TabWidth(0) {
+            return Err(vec![NcclError::new(
+                ErrorKind::Indentation,
+                "IndentPolicy::TabWidth must be at least 1",
+                0,
+            )]);
+        }
+
+        // `#!nccl2` or `#!nccl-lint` is some other shebang, not a pragma
+        if self.source.starts_with(b"#!nccl")
+            && matches!(
+                self.source.get(6),
+                None | Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n')
+            )
+        {
+            if let Err(e) = self.version_pragma() {
+                err.push(e);
+            }
+        }
+
         while !self.is_at_end() {
+            if self.current == 0 || self.source[self.current - 1] == b'\n' {
+                if let Err(e) = self.indentation() {
+                    err.push(e);
+                    self.skip_line();
+                }
+                if self.is_at_end() {
+                    break;
+                }
+            }
+
             self.start = self.current;
             let e = self.scan_token();
             if e.is_err() {
@@ -41,7 +98,7 @@ impl Scanner {
         }
 
         self.tokens
-            .push(Token::new(TokenKind::EOF, "".into(), self.line));
+            .push(Token::new(TokenKind::Eof, "".into(), self.line));
 
         if !err.is_empty() {
             Err(err)
@@ -50,92 +107,188 @@ impl Scanner {
         }
     }
 
-    fn scan_token(&mut self) -> Result<(), NcclError> {
-        let mut error = Ok(());
-        match self.advance() {
-            b'#' => {
-                while self.peek() != b'\n' && !self.is_at_end() {
-                    self.advance();
-                }
+    // `#!nccl N` on the first line selects the grammar of syntax version N
+    fn version_pragma(&mut self) -> Result<(), NcclError> {
+        let end = self.line_end(0);
+        let version = String::from_utf8_lossy(&self.source[6..end]);
+        let version = version.trim();
+        match version {
+            "1" => {
+                self.options.key_value_shorthand = false;
+                self.options.inline_lists = false;
+            }
+            "2" => {
+                self.options.key_value_shorthand = true;
+                self.options.inline_lists = true;
             }
+            _ => {
+                return Err(NcclError::new(
+                    ErrorKind::Parse,
+                    &format!(
+                        "Unsupported nccl syntax version {:?} in #!nccl pragma, expected 1 or 2",
+                        version
+                    ),
+                    1,
+                ))
+            }
+        }
 
-            b' ' => match self.indent {
-                Indent::Neither => {
-                    let mut spaces = 0;
-                    while self.peek() == b' ' && !self.is_at_end() {
-                        self.advance();
-                        spaces += 1;
-                    }
-                    if self.is_at_end() {
-                        error = Err(NcclError::new(
-                            ErrorKind::Parse,
-                            "Expected value, found EOF",
-                            self.line,
-                        ));
-                    }
-                    self.indent = Indent::Spaces(spaces);
-                    self.add_token(TokenKind::Indent)?;
-                }
-                Indent::Spaces(s) => {
-                    let mut spaces = 0;
-                    while spaces < s && !self.is_at_end() {
-                        if self.peek() != b' ' {
-                            error = Err(NcclError::new(
-                                ErrorKind::Indentation,
-                                &format!(
-                                    "Incorrect number of spaces: found {}, expected {}",
-                                    spaces, s
-                                ),
-                                self.line,
-                            ));
-                        }
-                        self.advance();
-                        spaces += 1;
-                    }
-                    if self.is_at_end() {
-                        error = Err(NcclError::new(
-                            ErrorKind::Parse,
-                            "Expected value, found EOF",
-                            self.line,
-                        ));
-                    }
-                    self.add_token(TokenKind::Indent)?;
-                }
-                Indent::Tabs => {
-                    error = Err(NcclError::new(
-                        ErrorKind::Indentation,
-                        "Expected tabs, found spaces",
-                        self.line,
+        Ok(())
+    }
+
+    // reads the whitespace at the start of a line, adding an Indent token for
+    // each level according to the indentation policy
+    fn indentation(&mut self) -> Result<(), NcclError> {
+        let start = self.current;
+        self.skip_whitespace();
+        let tabs = self.source[start..self.current]
+            .iter()
+            .filter(|&&c| c == b'\t')
+            .count();
+        let spaces = self.current - start - tabs;
+
+        // blank lines and comments don't need to line up, and don't start a
+        // new key
+        if self.rest_of_line_is_blank()
+            || (self.peek() == b'#'
+                && (tabs + spaces == 0 || self.source.get(self.current + 1) != Some(&b'[')))
+        {
+            return Ok(());
+        }
+
+        if tabs + spaces == 0 {
+            let key = &self.source[self.current..self.line_end(self.current)];
+            self.key = (self.line, String::from_utf8_lossy(key).trim().to_string());
+            if self.options.indent_policy != IndentPolicy::FileWide {
+                self.indent = Indent::Neither;
+            }
+            return Ok(());
+        }
+
+        let tab_width = match self.options.indent_policy {
+            IndentPolicy::TabWidth(n) => Some(n as usize),
+            _ => None,
+        };
+
+        let was_neither = self.indent == Indent::Neither;
+        let levels = match (self.indent, tab_width) {
+            (Indent::Neither, Some(n)) => {
+                self.indent = Indent::Spaces(spaces + tabs * n);
+                1
+            }
+            (Indent::Neither, None) if spaces == 0 => {
+                self.indent = Indent::Tabs;
+                tabs
+            }
+            (Indent::Neither, None) if tabs == 0 => {
+                self.indent = Indent::Spaces(spaces);
+                1
+            }
+            (Indent::Neither, None) => {
+                return Err(NcclError::new(
+                    ErrorKind::Indentation,
+                    "Found both tabs and spaces in indentation. Use one or the other, or \
+                     IndentPolicy::TabWidth to count tabs as spaces",
+                    self.line,
+                ));
+            }
+            (Indent::Tabs, _) if spaces > 0 => {
+                return Err(self.indentation_error("spaces", "with tabs"));
+            }
+            (Indent::Tabs, _) => tabs,
+            (Indent::Spaces(_), None) if tabs > 0 => {
+                return Err(self.indentation_error("tabs", "with spaces"));
+            }
+            (Indent::Spaces(n), tab_width) => {
+                let width = spaces + tabs * tab_width.unwrap_or(0);
+                if !width.is_multiple_of(n) {
+                    return Err(self.indentation_error(
+                        &format!("{} spaces", width),
+                        &format!("with a multiple of {} spaces", n),
                     ));
                 }
-            },
+                width / n
+            }
+        };
 
-            b'\t' => match self.indent {
-                Indent::Neither => {
-                    self.add_token(TokenKind::Indent)?;
-                    self.indent = Indent::Tabs;
-                }
-                Indent::Tabs => {
-                    self.add_token(TokenKind::Indent)?;
-                }
-                Indent::Spaces(_) => {
-                    error = Err(NcclError::new(
-                        ErrorKind::Indentation,
-                        "Expected spaces, found tabs",
-                        self.line,
-                    ));
+        if was_neither {
+            self.established = (self.line, self.key.1.clone());
+        }
+
+        for _ in 0..levels {
+            self.add_token_string(TokenKind::Indent, String::new());
+        }
+
+        Ok(())
+    }
+
+    fn indentation_error(&self, found: &str, fix: &str) -> NcclError {
+        let (line, key) = &self.established;
+        NcclError::new(
+            ErrorKind::Indentation,
+            &format!(
+                "Expected {}, found {}. Indentation was set to {} on line {} under {}; indent this \
+                 line {} instead",
+                self.indent, found, self.indent, line, key, fix
+            ),
+            self.line,
+        )
+    }
+
+    fn scan_token(&mut self) -> Result<(), NcclError> {
+        let mut error = Ok(());
+        match self.advance() {
+            b'#' => match self.peek() {
+                b'[' => {
+                    self.advance();
+                    if let Err(e) = self.block_comment() {
+                        error = Err(e);
+                    }
+                    self.skip_whitespace();
                 }
+                b'-' if self.source.get(self.current + 1) != Some(&b'-') => self.skip_subtree(),
+                _ => self.skip_line(),
             },
 
             b'\n' => {
                 self.add_token(TokenKind::Newline)?;
                 self.line += 1;
-                if self.peek() != b' ' && self.peek() != b'\t' && self.peek() != b'#' {
-                    self.indent = Indent::Neither;
+            }
+
+            b' ' | b'\t' | b'\r' => {}
+
+            b'-' if self.options.documents && self.is_document_separator() => {
+                self.current += 2;
+                self.add_token(TokenKind::Separator)?;
+                self.skip_line();
+                self.indent = Indent::Neither;
+            }
+
+            b'@' if self.at_line_start() && self.directive_name().is_some() => {
+                let name = self.directive_name().unwrap();
+                self.current += name.len();
+                self.add_token_string(TokenKind::Directive, name.into());
+                self.skip_whitespace();
+            }
+
+            b'|' if self.rest_of_line_is_blank() => {
+                if let Err(e) = self.block_string() {
+                    error = Err(e);
                 }
             }
 
-            b'\r' => {}
+            b'r' if self.raw_string_hashes().is_some() => {
+                if let Err(e) = self.raw_string() {
+                    error = Err(e);
+                }
+            }
+
+            b'[' if self.options.inline_lists => {
+                if let Err(e) = self.inline_list() {
+                    self.skip_line();
+                    error = Err(e);
+                }
+            }
 
             b'"' => {
                 if let Err(e) = self.string() {
@@ -144,7 +297,7 @@ impl Scanner {
             }
 
             _ => {
-                if let Err(e) = self.identifier() {
+                if let Err(e) = self.identifier(true) {
                     error = Err(e);
                 }
             }
@@ -153,21 +306,59 @@ impl Scanner {
         error
     }
 
-    fn identifier(&mut self) -> Result<(), NcclError> {
+    // keys may be followed by `= value` or an inline list when those are
+    // enabled, values may not
+    fn identifier(&mut self, key: bool) -> Result<(), NcclError> {
         loop {
             if self.peek() == b'\n' || self.peek() == b'\r' || self.is_at_end() {
                 break;
-            } else if self.peek() == b'#' {
+            } else if key && self.options.key_value_shorthand && self.is_shorthand_equals() {
+                let result = self.shorthand();
+                if result.is_err() {
+                    self.skip_line();
+                }
+                return result;
+            } else if key
+                && self.options.inline_lists
+                && self.peek() == b'['
+                && (self.current == self.start
+                    || self.source[self.current - 1] == b' '
+                    || self.source[self.current - 1] == b'\t')
+            {
+                let key = self.bare(self.start, self.current)?;
+                if !key.trim_end().is_empty() {
+                    self.add_token_string(TokenKind::Value, key.trim_end().into());
+                }
+
+                self.advance();
+                let result = self.inline_list();
+                if result.is_err() {
+                    self.skip_line();
+                }
+                return result;
+            } else if self.peek() == b'#' && self.starts_comment() {
+                let hash = self.current;
                 while (self.reverse() as char).is_whitespace() {}
                 self.advance();
 
-                let value = String::from_utf8(self.source[self.start..self.current].to_vec())
-                    .map_err(|err| {
-                        NcclError::new(ErrorKind::Utf8 { err }, "invalid UTF-8", self.line)
-                    })?;
+                let value = self.bare(self.start, self.current)?;
                 self.add_token_string(TokenKind::Value, value);
 
-                while self.advance() != b'\n' {}
+                self.current = hash + 1;
+                if self.peek() == b'[' {
+                    self.advance();
+                    self.block_comment()?;
+                    self.skip_whitespace();
+                    if !self.is_at_end() && !matches!(self.peek(), b'\n' | b'\r' | b'#') {
+                        self.skip_line();
+                        return Err(NcclError::new(
+                            ErrorKind::Parse,
+                            "Expected the end of the line after a block comment",
+                            self.line,
+                        ));
+                    }
+                }
+                self.skip_line();
 
                 return Ok(());
             } else {
@@ -175,52 +366,207 @@ impl Scanner {
             }
         }
 
-        let value = String::from_utf8(self.source[self.start..self.current].to_vec())
-            .map_err(|err| NcclError::new(ErrorKind::Utf8 { err }, "invalid UTF-8", self.line))?;
-        self.add_token_string(TokenKind::Value, value);
+        let value = self.bare(self.start, self.current)?;
+        let trimmed = value.trim_end_matches([' ', '\t']);
+        self.add_token_string(TokenKind::Value, trimmed.into());
+
+        if trimmed.len() != value.len() && !self.options.allow_trailing_whitespace {
+            Err(self.trailing_whitespace())
+        } else {
+            Ok(())
+        }
+    }
+
+    // `a.b = c` on one line scans the same as c indented under b under a
+    fn shorthand(&mut self) -> Result<(), NcclError> {
+        let key = self.bare(self.start, self.current)?;
+        for segment in key.split('.') {
+            let segment = segment.trim();
+            if segment.is_empty() {
+                return Err(NcclError::new(
+                    ErrorKind::Parse,
+                    &format!("Empty key in {}", key.trim()),
+                    self.line,
+                ));
+            }
+            self.add_token_string(TokenKind::Value, segment.into());
+        }
+
+        self.advance();
+        self.skip_whitespace();
+
+        if self.is_at_end() || matches!(self.peek(), b'\n' | b'\r' | b'#') {
+            return Err(NcclError::new(
+                ErrorKind::Parse,
+                &format!("Expected a value after {} =", key.trim()),
+                self.line,
+            ));
+        }
+
+        self.start = self.current;
+        match self.peek() {
+            b'[' if self.options.inline_lists => {
+                self.advance();
+                self.inline_list()
+            }
+            b'"' => {
+                self.advance();
+                self.string()
+            }
+            b'r' => {
+                self.advance();
+                if self.raw_string_hashes().is_some() {
+                    self.raw_string()
+                } else {
+                    self.identifier(false)
+                }
+            }
+            _ => self.identifier(false),
+        }
+    }
+
+    // `[a, "b, c"]` makes each item a child of the values before it on the
+    // same line
+    fn inline_list(&mut self) -> Result<(), NcclError> {
+        self.add_token_string(TokenKind::ListStart, "[".into());
+
+        loop {
+            self.skip_whitespace();
+            if self.is_at_end() || self.peek() == b'\n' || self.peek() == b'\r' {
+                return Err(NcclError::new(
+                    ErrorKind::Parse,
+                    "Unterminated list, expected ]",
+                    self.line,
+                ));
+            }
+
+            match self.peek() {
+                // allows [] and a trailing comma
+                b']' => break,
+                b'"' => {
+                    self.advance();
+                    let item = self.quoted()?;
+                    self.add_token_string(TokenKind::Quoted, item);
+                }
+                b'r' if self
+                    .source
+                    .get(self.current + 1)
+                    .is_some_and(|&c| c == b'"' || c == b'#') =>
+                {
+                    self.advance();
+                    if self.raw_string_hashes().is_some() {
+                        let item = self.raw()?;
+                        self.add_token_string(TokenKind::Quoted, item);
+                    } else {
+                        self.reverse();
+                        self.list_item()?;
+                    }
+                }
+                _ => self.list_item()?,
+            }
+
+            self.skip_whitespace();
+            match self.peek() {
+                b',' => {
+                    self.advance();
+                }
+                b']' => break,
+                _ => {
+                    return Err(NcclError::new(
+                        ErrorKind::Parse,
+                        "Expected , or ] after list item",
+                        self.line,
+                    ))
+                }
+            }
+        }
+
+        self.advance();
+        self.add_token_string(TokenKind::ListEnd, "]".into());
+        self.end_of_line("list")
+    }
+
+    fn list_item(&mut self) -> Result<(), NcclError> {
+        let start = self.current;
+        while !self.is_at_end() && !matches!(self.peek(), b',' | b']' | b'\n' | b'\r') {
+            self.advance();
+        }
+
+        let item = self.bare(start, self.current)?;
+        if item.trim().is_empty() {
+            return Err(NcclError::new(
+                ErrorKind::Parse,
+                "Empty item in list",
+                self.line,
+            ));
+        }
 
+        self.add_token_string(TokenKind::Value, item.trim().into());
         Ok(())
     }
 
     fn string(&mut self) -> Result<(), NcclError> {
-        let mut value = String::new();
+        let value = self.quoted();
+        if value.is_err() {
+            self.skip_line();
+        }
+        self.add_token_string(TokenKind::Quoted, value?);
+        self.end_of_line("closing quote")
+    }
+
+    // reads a quoted string up to and including the closing quote
+    fn quoted(&mut self) -> Result<String, NcclError> {
+        let mut value = Vec::new();
+        let mut error = None;
         while self.peek() != b'"' && !self.is_at_end() {
             if self.peek() == b'\n' {
                 self.line += 1;
             }
 
             if self.peek() == b'\\' {
+                let backslash = self.current;
                 self.advance();
                 match self.peek() {
-                    b'n' => {
-                        value.push('\n');
-                    }
-                    b'r' => {
-                        value.push('\r');
-                    }
-                    b'\\' => {
-                        value.push('\\');
-                    }
-                    b'"' => {
-                        value.push('"');
+                    b'n' => value.push(b'\n'),
+                    b'r' => value.push(b'\r'),
+                    b't' => value.push(b'\t'),
+                    b'0' => value.push(b'\0'),
+                    b'\\' => value.push(b'\\'),
+                    b'"' => value.push(b'"'),
+                    b'x' | b'u' => {
+                        match self.escape() {
+                            Some(c) => {
+                                let mut buf = [0; 4];
+                                value.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                            }
+                            None => {
+                                error = error.or_else(|| Some(self.escape_error(backslash)));
+                            }
+                        }
+                        continue;
                     }
                     b'\r' | b'\n' => {
-                        self.advance();
+                        if self.peek() == b'\r' {
+                            self.advance();
+                        }
+                        if self.peek() == b'\n' {
+                            self.advance();
+                            self.line += 1;
+                        }
                         while self.peek() == b' ' || self.peek() == b'\t' {
                             self.advance();
                         }
-                        self.reverse();
+                        continue;
                     }
                     _ => {
-                        return Err(NcclError::new(
-                            ErrorKind::Parse,
-                            &format!("Unknown format code: {}", self.peek()),
-                            self.line,
-                        ))
+                        error = error.or_else(|| Some(self.escape_error(backslash)));
+                        if self.is_at_end() {
+                            break;
+                        }
                     }
                 }
             } else {
-                value.push(self.source[self.current] as char);
+                value.push(self.source[self.current]);
             }
 
             self.advance();
@@ -236,12 +582,237 @@ impl Scanner {
 
         self.advance();
 
-        self.add_token_string(TokenKind::Value, value);
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        String::from_utf8(value)
+            .map_err(|err| NcclError::new(ErrorKind::Utf8 { err }, "invalid UTF-8", self.line))
+    }
+
+    // number of #s between the r and the opening quote of a raw string
+    fn raw_string_hashes(&self) -> Option<usize> {
+        let hashes = self.source[self.current..]
+            .iter()
+            .take_while(|&&c| c == b'#')
+            .count();
+        if self.source.get(self.current + hashes) == Some(&b'"') {
+            Some(hashes)
+        } else {
+            None
+        }
+    }
+
+    fn raw_string(&mut self) -> Result<(), NcclError> {
+        let value = self.raw();
+        if value.is_err() {
+            self.skip_line();
+        }
+        self.add_token_string(TokenKind::Raw, value?);
+        self.end_of_line("raw string")
+    }
+
+    // reads a raw string, starting after the r
+    fn raw(&mut self) -> Result<String, NcclError> {
+        let hashes = self.raw_string_hashes().unwrap_or(0);
+        self.current += hashes + 1;
+
+        let start = self.current;
+        loop {
+            if self.is_at_end() {
+                return Err(NcclError::new(
+                    ErrorKind::Parse,
+                    "Unterminated raw string",
+                    self.line,
+                ));
+            }
+
+            if self.peek() == b'"'
+                && self.source[self.current + 1..]
+                    .iter()
+                    .take(hashes)
+                    .filter(|&&c| c == b'#')
+                    .count()
+                    == hashes
+            {
+                break;
+            }
+
+            if self.advance() == b'\n' {
+                self.line += 1;
+            }
+        }
+
+        let value = String::from_utf8(self.source[start..self.current].to_vec())
+            .map_err(|err| NcclError::new(ErrorKind::Utf8 { err }, "invalid UTF-8", self.line));
+        self.current += hashes + 1;
+        value
+    }
 
-        while self.peek() != b'\n' {
+    // reads the rest of a \xNN or \u{XXXX} escape, stopping at the closing
+    // quote if the escape is malformed
+    fn escape(&mut self) -> Option<char> {
+        let (digits, max) = if self.advance() == b'x' {
+            let digits = self.escape_digits(2);
+            (digits.filter(|d| d.len() == 2), 0x7f)
+        } else {
+            if self.peek() != b'{' {
+                return None;
+            }
             self.advance();
+            let digits = self.escape_digits(6);
+            if self.peek() != b'}' {
+                return None;
+            }
+            self.advance();
+            (digits, 0x10ffff)
+        };
+
+        u32::from_str_radix(&digits?, 16)
+            .ok()
+            .filter(|&c| c <= max)
+            .and_then(std::char::from_u32)
+    }
+
+    fn escape_digits(&mut self, max: usize) -> Option<String> {
+        let mut digits = String::new();
+        while digits.len() < max && self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance() as char);
+        }
+
+        if digits.is_empty() {
+            None
+        } else {
+            Some(digits)
+        }
+    }
+
+    fn escape_error(&self, backslash: usize) -> NcclError {
+        let escape = String::from_utf8_lossy(&self.source[backslash..])
+            .chars()
+            .take(2)
+            .collect::<String>();
+        NcclError::new(
+            ErrorKind::Parse,
+            &format!(
+                "Invalid escape sequence {} at column {}",
+                escape.trim_end(),
+                self.column(backslash)
+            ),
+            self.line,
+        )
+    }
+
+    // one-based column of a byte offset, counted in characters
+    fn column(&self, offset: usize) -> usize {
+        let line_start = self.line_start(offset);
+        String::from_utf8_lossy(&self.source[line_start..offset])
+            .chars()
+            .count()
+            + 1
+    }
+
+    // a `|` alone on a line introduces a block of lines indented deeper than
+    // it, kept verbatim apart from their common leading indentation
+    fn block_string(&mut self) -> Result<(), NcclError> {
+        let marker_width = self.start - self.line_start(self.start);
+        let line = self.line;
+
+        // (start, start of text, end, is blank) for each line in the block
+        let mut lines = vec![];
+        let mut next = self.line_end(self.current);
+        let mut end = next;
+        while next < self.source.len() {
+            let line_start = next + 1;
+            let mut text = line_start;
+            while text < self.source.len()
+                && (self.source[text] == b' ' || self.source[text] == b'\t')
+            {
+                text += 1;
+            }
+            let line_end = self.line_end(text);
+
+            let blank = self.source[text..line_end].iter().all(|&c| c == b'\r');
+            if !blank && text - line_start <= marker_width {
+                break;
+            }
+
+            lines.push((line_start, text, line_end, blank));
+            if !blank {
+                end = line_end;
+            }
+            next = line_end;
+        }
+
+        // trailing blank lines belong to the rest of the file
+        while lines.last().is_some_and(|&(_, _, _, blank)| blank) {
+            lines.pop();
+        }
+
+        if lines.is_empty() {
+            return Err(NcclError::new(
+                ErrorKind::Parse,
+                "Expected an indented block after |",
+                self.line,
+            ));
         }
 
+        let common = lines
+            .iter()
+            .filter(|&&(_, _, _, blank)| !blank)
+            .map(|&(start, text, _, _)| text - start)
+            .min()
+            .unwrap_or(0);
+
+        let expected = match self.indent {
+            Indent::Tabs => b'\t',
+            Indent::Spaces(_) => b' ',
+            Indent::Neither => lines
+                .iter()
+                .find(|&&(_, _, _, blank)| !blank)
+                .map_or(b' ', |&(start, _, _, _)| self.source[start]),
+        };
+
+        let mut value = Vec::new();
+        for (i, &(start, _, line_end, blank)) in lines.iter().enumerate() {
+            if i > 0 {
+                value.push(b'\n');
+            }
+
+            if blank {
+                continue;
+            }
+
+            if !matches!(self.options.indent_policy, IndentPolicy::TabWidth(_))
+                && self.source[start..start + common]
+                    .iter()
+                    .any(|&c| c != expected)
+            {
+                return Err(NcclError::new(
+                    ErrorKind::Indentation,
+                    if expected == b' ' {
+                        "Expected spaces, found tabs in block string"
+                    } else {
+                        "Expected tabs, found spaces in block string"
+                    },
+                    line + i as u64 + 1,
+                ));
+            }
+
+            let mut text_end = line_end;
+            if text_end > start + common && self.source[text_end - 1] == b'\r' {
+                text_end -= 1;
+            }
+            value.extend_from_slice(&self.source[start + common..text_end]);
+        }
+
+        let value = String::from_utf8(value)
+            .map_err(|err| NcclError::new(ErrorKind::Utf8 { err }, "invalid UTF-8", self.line))?;
+        self.add_token_string(TokenKind::Quoted, value);
+
+        self.line += lines.len() as u64;
+        self.current = end;
+
         Ok(())
     }
 
@@ -256,6 +827,192 @@ impl Scanner {
         self.tokens.push(Token::new(kind, value, self.line));
     }
 
+    // skips a `#[ ... ]#` comment, which may span lines and nest, starting
+    // after the #[
+    fn block_comment(&mut self) -> Result<(), NcclError> {
+        let line = self.line;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(NcclError::new(
+                    ErrorKind::Parse,
+                    "Unterminated block comment, expected ]#",
+                    line,
+                ));
+            }
+
+            let next = self.source.get(self.current + 1).copied();
+            match (self.advance(), next) {
+                (b'#', Some(b'[')) => {
+                    self.advance();
+                    depth += 1;
+                }
+                (b']', Some(b'#')) => {
+                    self.advance();
+                    depth -= 1;
+                }
+                (b'\n', _) => self.line += 1,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // `#-` before a key comments out that line and every line indented
+    // deeper than it
+    fn skip_subtree(&mut self) {
+        let width = self.start - self.line_start(self.start);
+        self.skip_line();
+
+        while !self.is_at_end() {
+            let line_start = self.current + 1;
+            let mut text = line_start;
+            while self.source.get(text) == Some(&b' ') || self.source.get(text) == Some(&b'\t') {
+                text += 1;
+            }
+
+            let line_end = self.line_end(text);
+            let blank = self.source[text..line_end].iter().all(|&c| c == b'\r');
+            if !blank && text - line_start <= width {
+                break;
+            }
+
+            self.current = line_end;
+            self.line += 1;
+        }
+    }
+
+    // a # only starts a comment after whitespace, so `example.com/#install`
+    // stays whole, unless the old rules are asked for. `\#` never does.
+    // only a ` = ` with whitespace on both sides is the shorthand, so values
+    // like `http://x?a=b` and `abc==` are left alone
+    fn is_shorthand_equals(&self) -> bool {
+        self.source.get(self.current) == Some(&b'=')
+            && matches!(self.source[..self.current].last(), Some(b' ') | Some(b'\t'))
+            && matches!(
+                self.source.get(self.current + 1),
+                None | Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n')
+            )
+    }
+
+    fn starts_comment(&self) -> bool {
+        match self.source[..self.current].last() {
+            Some(b'\\') => false,
+            Some(b' ') | Some(b'\t') => true,
+            _ => self.options.legacy_comments,
+        }
+    }
+
+    // text of a bare word, with `\#` unescaped
+    fn bare(&self, start: usize, end: usize) -> Result<String, NcclError> {
+        String::from_utf8(self.source[start..end].to_vec())
+            .map(|text| text.replace("\\#", "#"))
+            .map_err(|err| NcclError::new(ErrorKind::Utf8 { err }, "invalid UTF-8", self.line))
+    }
+
+    // `---` alone at the start of a line separates documents
+    fn is_document_separator(&self) -> bool {
+        self.start == self.line_start(self.start)
+            && self.source[self.start..].starts_with(b"---")
+            && self.source[self.start + 3..]
+                .iter()
+                .take_while(|&&c| c != b'\n')
+                .all(|&c| c == b' ' || c == b'\t' || c == b'\r')
+    }
+
+    // only whitespace and comments may follow a closing quote or bracket
+    fn end_of_line(&mut self, after: &str) -> Result<(), NcclError> {
+        let whitespace = self.current;
+        self.skip_whitespace();
+
+        let result = if self.is_at_end() || matches!(self.peek(), b'\n' | b'\r') {
+            if self.current > whitespace && !self.options.allow_trailing_whitespace {
+                Err(self.trailing_whitespace())
+            } else {
+                Ok(())
+            }
+        } else if self.peek() == b'#' || self.options.allow_trailing_text {
+            Ok(())
+        } else {
+            let text = &self.source[self.current..self.line_end(self.current)];
+            Err(NcclError::new(
+                ErrorKind::Parse,
+                &format!(
+                    "Unexpected text after {}: {}",
+                    after,
+                    String::from_utf8_lossy(text).trim_end()
+                ),
+                self.line,
+            ))
+        };
+
+        self.skip_line();
+        result
+    }
+
+    fn trailing_whitespace(&self) -> NcclError {
+        NcclError::new(
+            ErrorKind::Parse,
+            "Trailing whitespace at the end of the line",
+            self.line,
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek() == b' ' || self.peek() == b'\t' {
+            self.advance();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while self.peek() != b'\n' && !self.is_at_end() {
+            self.advance();
+        }
+    }
+
+    // a directive is a known `@name` followed by whitespace as the first
+    // thing on a line, anything else starting with @ is a bare word
+    fn directive_name(&self) -> Option<&'static str> {
+        DIRECTIVES.iter().copied().find(|name| {
+            self.source[self.current..].starts_with(name.as_bytes())
+                && matches!(
+                    self.source.get(self.current + name.len()),
+                    Some(b' ') | Some(b'\t')
+                )
+        })
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.tokens.last().is_none_or(|token| {
+            matches!(
+                token.kind,
+                TokenKind::Newline | TokenKind::Indent | TokenKind::Separator
+            )
+        })
+    }
+
+    fn rest_of_line_is_blank(&self) -> bool {
+        self.source[self.current..]
+            .iter()
+            .take_while(|&&c| c != b'\n')
+            .all(|&c| c == b' ' || c == b'\t' || c == b'\r')
+    }
+
+    fn line_end(&self, offset: usize) -> usize {
+        self.source[offset..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(self.source.len(), |i| offset + i)
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        self.source[..offset]
+            .iter()
+            .rposition(|&c| c == b'\n')
+            .map_or(0, |i| i + 1)
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
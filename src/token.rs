@@ -1,9 +1,15 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
     Value,
+    Quoted,
+    Raw,
+    ListStart,
+    ListEnd,
+    Separator,
+    Directive,
     Indent,
     Newline,
-    EOF,
+    Eof,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -1,8 +1,14 @@
-use std::convert::TryInto;
+use crate::options::{ParseOptions, SpecialFloats};
+
+use std::convert::{TryFrom, TryInto};
 
 use std::fmt;
 
-/// Parses a String into a Value, first attempting bool, i64, and f64.
+/// Parses a String into a Value, first attempting null, bool, i64, and f64.
+///
+/// Integers may be written in decimal or with a `0x`, `0o` or `0b` prefix,
+/// and digits may be separated with underscores. The words `inf` and `nan`
+/// are left as strings; use `parse_into_value_with` to change that.
 ///
 /// Examples:
 ///
@@ -17,24 +23,138 @@ use std::fmt;
 ///     Value::String(s) => println!("none of the above: {}", s),
 ///     _ => panic!("it's really broke yo")
 /// }
+///
+/// assert_eq!(parse_into_value("0o755".into()), Value::Integer(0o755));
+/// assert_eq!(parse_into_value("1_000_000".into()), Value::Integer(1_000_000));
 /// ```
 pub fn parse_into_value(into: String) -> Value {
-    if let Ok(b) = into.parse::<bool>() {
+    parse_into_value_with(into, &ParseOptions::default())
+}
+
+/// Parses a String into a Value using the given options.
+///
+/// Examples:
+///
+/// ```
+/// # use nccl::{Value, ParseOptions, SpecialFloats, parse_into_value_with};
+/// let options = ParseOptions::new().special_floats(SpecialFloats::Floats);
+/// match parse_into_value_with("-inf".into(), &options) {
+///     Value::Float(f) => assert!(f.is_infinite()),
+///     _ => panic!("should be a float"),
+/// }
+/// ```
+pub fn parse_into_value_with(into: String, options: &ParseOptions) -> Value {
+    if options.null_keywords.contains(&into) {
+        return Value::Null;
+    }
+    if let Some(b) = options.bool_vocabulary.parse(&into) {
         return Value::Bool(b);
     }
-    if let Ok(i) = into.parse::<i64>() {
+    if let Some(i) = parse_integer(&into) {
         return Value::Integer(i);
     }
-    if let Ok(f) = into.parse::<f64>() {
+    if let Some(f) = parse_float(&into, options.special_floats) {
         return Value::Float(f);
     }
 
     Value::String(into)
 }
 
+fn split_sign(s: &str) -> (bool, &str) {
+    match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    }
+}
+
+// removes underscores, which are only allowed between two digits
+fn strip_separators(digits: &str, radix: u32) -> Option<String> {
+    let bytes = digits.as_bytes();
+    let is_digit = |i: usize| bytes.get(i).is_some_and(|&c| (c as char).is_digit(radix));
+
+    let mut stripped = String::with_capacity(digits.len());
+    for (i, &c) in bytes.iter().enumerate() {
+        if c == b'_' {
+            if i == 0 || !is_digit(i - 1) || !is_digit(i + 1) {
+                return None;
+            }
+        } else {
+            stripped.push(c as char);
+        }
+    }
+
+    Some(stripped)
+}
+
+fn parse_integer(s: &str) -> Option<i64> {
+    let (negative, unsigned) = split_sign(s);
+    let (radix, digits) = match unsigned.get(..2) {
+        Some("0x") | Some("0X") => (16, &unsigned[2..]),
+        Some("0o") | Some("0O") => (8, &unsigned[2..]),
+        Some("0b") | Some("0B") => (2, &unsigned[2..]),
+        _ => (10, unsigned),
+    };
+
+    let digits = strip_separators(digits, radix)?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    let magnitude = u64::from_str_radix(&digits, radix).ok()?;
+    if negative {
+        if magnitude <= i64::MAX as u64 + 1 {
+            Some((magnitude as i64).wrapping_neg())
+        } else {
+            None
+        }
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+fn parse_float(s: &str, special: SpecialFloats) -> Option<f64> {
+    let (_, unsigned) = split_sign(s);
+    if ["inf", "infinity", "nan"]
+        .iter()
+        .any(|word| unsigned.eq_ignore_ascii_case(word))
+    {
+        return match special {
+            SpecialFloats::Floats => s.parse::<f64>().ok(),
+            SpecialFloats::Strings => None,
+        };
+    }
+
+    // [sign] digits [. digits] [(e|E) [sign] digits]
+    let stripped = strip_separators(unsigned, 10)?;
+    let (mantissa, exponent) = match stripped.find(['e', 'E']) {
+        Some(i) => (&stripped[..i], Some(&stripped[i + 1..])),
+        None => (&stripped[..], None),
+    };
+
+    let mut parts = mantissa.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    let all_digits = |part: &str| part.bytes().all(|c| c.is_ascii_digit());
+    if whole.len() + fraction.len() == 0 || !all_digits(whole) || !all_digits(fraction) {
+        return None;
+    }
+
+    if let Some(exponent) = exponent {
+        let (_, digits) = split_sign(exponent);
+        if digits.is_empty() || !all_digits(digits) {
+            return None;
+        }
+    }
+
+    s.replace('_', "").parse::<f64>().ok()
+}
+
 #[derive(Debug, PartialEq, Clone)]
 /// Wrapper type for possible types in nccl configuration.
 pub enum Value {
+    /// A deliberately unset value, written as a bare `~` by default.
+    Null,
     String(String),
     Bool(bool),
     Integer(i64),
@@ -121,6 +241,24 @@ impl TryInto<f32> for Value {
     }
 }
 
+macro_rules! try_into_option {
+    ($($t:ty),*) => {
+        $(
+            impl TryInto<Option<$t>> for Value {
+                type Error = ();
+                fn try_into(self) -> Result<Option<$t>, Self::Error> {
+                    match self {
+                        Value::Null => Ok(None),
+                        v => v.try_into().map(Some),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+try_into_option!(String, bool, i64, i32, u64, u32, f64, f32);
+
 impl<'a> From<&'a Value> for Value {
     fn from(v: &'a Value) -> Self {
         v.clone()
@@ -187,9 +325,19 @@ impl From<f32> for Value {
     }
 }
 
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Self {
+        match o {
+            Some(v) => v.into(),
+            None => Value::Null,
+        }
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Value::Null => write!(f, "~"),
             Value::Bool(b) => write!(f, "{}", b),
             Value::String(ref s) => write!(f, "{}", s),
             Value::Float(fl) => write!(f, "{}", fl),
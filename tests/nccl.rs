@@ -132,7 +132,20 @@ fn comments() {
     let z = parse_file("examples/comments.nccl").unwrap();
     z.pretty_print();
     assert_eq!("bone hurting juice", z["oof ouch owie"].value().unwrap());
-    assert_eq!("another one!", z["no quotes as well"].value().unwrap());
+    assert_eq!(
+        vec!["another one!", "this should work afterward"],
+        z["no quotes as well"].keys_as::<String>().unwrap()
+    );
+    assert_eq!(
+        "truly",
+        z["no quotes as well"]["this should work afterward"]
+            .value()
+            .unwrap()
+    );
+    assert_eq!("perhaps?#", z["at the end"].value().unwrap());
+
+    let options = ParseOptions::new().legacy_comments(true);
+    let z = parse_file_with_options("examples/comments.nccl", &options).unwrap();
     assert_eq!("perhaps?", z["at the end"].value().unwrap());
 }
 
@@ -215,3 +228,944 @@ does this work?
         .get_ref("is this a child?")
         .unwrap();
 }
+
+#[test]
+fn numeric_literals() {
+    let config = parse_file("examples/numbers.nccl").unwrap();
+    assert_eq!(config["mode"].value_as::<i64>().unwrap(), 0o755);
+    assert_eq!(config["mask"].value_as::<i64>().unwrap(), 0xFF);
+    assert_eq!(config["flags"].value_as::<i64>().unwrap(), 0b1010_1010);
+    assert_eq!(config["population"].value_as::<i64>().unwrap(), 1_000_000);
+    assert_eq!(config["tiny"].value_as::<f64>().unwrap(), 6.02e-23);
+    assert_eq!(config["huge"].value_as::<f64>().unwrap(), -1.5e10);
+    assert_eq!(config["host"].value_as::<String>().unwrap(), "inf");
+    assert_eq!(config["nothing"].value_as::<String>().unwrap(), "nan");
+}
+
+#[test]
+fn numeric_literals_malformed() {
+    for s in &[
+        "0x",
+        "1__000",
+        "_1",
+        "1_",
+        "0xG1",
+        "1._5",
+        "1e",
+        "2017-03-21",
+    ] {
+        assert_eq!(
+            parse_into_value(s.to_string()),
+            Value::String(s.to_string())
+        );
+    }
+    assert_eq!(parse_into_value("-0x10".into()), Value::Integer(-16));
+    assert_eq!(
+        parse_into_value("-0x8000_0000_0000_0000".into()),
+        Value::Integer(i64::MIN)
+    );
+    assert_eq!(
+        parse_into_value("0x8000_0000_0000_0000".into()),
+        Value::String("0x8000_0000_0000_0000".into())
+    );
+}
+
+#[test]
+fn special_floats() {
+    let options = ParseOptions::new().special_floats(SpecialFloats::Floats);
+    let config = parse_file_with_options("examples/numbers.nccl", &options).unwrap();
+    assert!(config["host"].value_as::<f64>().unwrap().is_infinite());
+    assert!(config["nothing"].value_as::<f64>().unwrap().is_nan());
+}
+
+#[test]
+fn bool_vocabulary() {
+    let source = "a\n    yes\nb\n    Off\nc\n    True\nd\n    maybe\n";
+
+    let strict = parse_string(source).unwrap();
+    assert!(strict["a"].value_as::<bool>().is_err());
+    assert_eq!(strict["c"].value_as::<String>().unwrap(), "True");
+
+    let options = ParseOptions::new().bool_vocabulary(BoolVocabulary::lenient());
+    let lenient = parse_string_with_options(source, &options).unwrap();
+    assert!(lenient["a"].value_as::<bool>().unwrap());
+    assert!(!lenient["b"].value_as::<bool>().unwrap());
+    assert!(lenient["c"].value_as::<bool>().unwrap());
+    assert!(lenient["d"].value_as::<bool>().is_err());
+
+    let custom = ParseOptions::new().bool_vocabulary(BoolVocabulary::new(&["maybe"], &[]));
+    let custom = parse_string_with_options(source, &custom).unwrap();
+    assert!(custom["d"].value_as::<bool>().unwrap());
+}
+
+#[test]
+fn value_as_bool_lenient() {
+    let config = parse_string("a\n    enabled\nb\n    0\nc\n    false\nd\n    2\n").unwrap();
+    assert!(config["a"].value_as_bool_lenient().unwrap());
+    assert!(!config["b"].value_as_bool_lenient().unwrap());
+    assert!(!config["c"].value_as_bool_lenient().unwrap());
+    assert!(config["d"].value_as_bool_lenient().is_err());
+}
+
+#[test]
+fn null_values() {
+    let config = parse_file("examples/null.nccl").unwrap();
+    assert_eq!(config["proxy"].value_as::<Option<String>>().unwrap(), None);
+    assert!(config["proxy"].value_as::<String>().is_err());
+    assert_eq!(
+        config["home"].value_as::<Option<String>>().unwrap(),
+        Some("~".into())
+    );
+    assert_eq!(config["name"].value_as::<String>().unwrap(), "");
+    assert_eq!(
+        config["name"].value_as::<Option<String>>().unwrap(),
+        Some("".into())
+    );
+
+    let config = parse_string("port\n    8080").unwrap();
+    assert_eq!(
+        config["port"].value_as::<Option<i64>>().unwrap(),
+        Some(8080)
+    );
+}
+
+#[test]
+fn null_keywords() {
+    let options = ParseOptions::new().null_keywords(&[]);
+    let config = parse_file_with_options("examples/null.nccl", &options).unwrap();
+    assert_eq!(config["proxy"].value_as::<String>().unwrap(), "~");
+
+    let mut p = Pair::new("top");
+    p.add("unset");
+    p["unset"].add(None::<i64>);
+    assert_eq!(p["unset"].value_as::<Option<i64>>().unwrap(), None);
+}
+
+#[test]
+fn unicode_strings() {
+    let config = parse_file("examples/unicode.nccl").unwrap();
+    assert_eq!(config["greeting"].value().unwrap(), "héllo wörld ✓");
+    assert_eq!(
+        config["escapes"].value().unwrap(),
+        "tab\there\0nul A \u{1F600} é"
+    );
+    assert_eq!(config["bare"].value().unwrap(), "naïve café");
+
+    let config = parse_string("quoted\n    \"at the end\"").unwrap();
+    assert_eq!(config["quoted"].value().unwrap(), "at the end");
+}
+
+#[test]
+fn invalid_escapes() {
+    for source in &[
+        "a\n    \"ü \\q\"",
+        "a\n    \"ü \\x8F\"",
+        "a\n    \"ü \\xZ\"",
+        "a\n    \"ü \\u{D800}\"",
+        "a\n    \"ü \\u{110000}\"",
+        "a\n    \"ü \\u00e9\"",
+    ] {
+        let err = parse_string(source).unwrap_err();
+        assert_eq!(err.len(), 1);
+        assert!(format!("{}", err[0]).contains("at column 8"), "{}", err[0]);
+    }
+}
+
+#[test]
+fn block_strings() {
+    let config = parse_file("examples/block.nccl").unwrap();
+    assert_eq!(
+        config["certificate"].value().unwrap(),
+        "-----BEGIN CERTIFICATE-----\nMIIBszCCAVmgAwIBAgIU\n-----END CERTIFICATE-----"
+    );
+    assert_eq!(
+        config["script"].value().unwrap(),
+        "#!/bin/sh\nif [ -n \"$1\" ]; then\n    echo \"hello, $1\"\n\n    exit 0\nfi"
+    );
+    assert_eq!(
+        config["query"].keys_as::<String>().unwrap(),
+        vec!["SELECT *\n  FROM users\n WHERE name = \"~\"", "timeout"]
+    );
+    assert_eq!(config["query"]["timeout"].value_as::<i64>().unwrap(), 30);
+}
+
+#[test]
+fn block_string_errors() {
+    assert!(parse_string("a\n    |\nb\n    c\n").is_err());
+    assert!(parse_string("a\n    |\n        one\n    \t    two\n").is_err());
+
+    let source = "a\n    |\n        one\n\n        two\nb\n    c\n            d\n";
+    let err = parse_string(source).unwrap_err();
+    assert!(format!("{}", err[0]).contains("line 8"), "{}", err[0]);
+}
+
+#[test]
+fn raw_strings() {
+    let config = parse_file("examples/raw.nccl").unwrap();
+    assert_eq!(config["regex"].value().unwrap(), r"^\d{3}-\d{4}$");
+    assert_eq!(config["path"].value().unwrap(), r"C:\Users\nccl\config");
+    assert_eq!(config["quotes"].value().unwrap(), r#"she said "hi" to C:\"#);
+    assert_eq!(config["rust"].value().unwrap(), "really bare");
+
+    assert!(parse_string("a\n    r#\"unterminated\"\n").is_err());
+}
+
+#[test]
+fn key_value_shorthand() {
+    let options = ParseOptions::new().key_value_shorthand(true);
+    let short = parse_string_with_options(
+        "server.domain = example.com\nserver.port = 80 # http\nserver.port = 443\nserver.root = \"/var/www/html\"\n",
+        &options,
+    )
+    .unwrap();
+    let long = parse_file("examples/config.nccl").unwrap();
+    assert_eq!(short["server"]["port"], long["server"]["port"]);
+    assert_eq!(short["server"]["root"], long["server"]["root"]);
+
+    let mixed = parse_string_with_options(
+        "server\n    port = 8080\n    tls\n        enabled = true\n    query = a=b\n",
+        &options,
+    )
+    .unwrap();
+    assert_eq!(mixed["server"]["port"].value_as::<i64>().unwrap(), 8080);
+    assert!(mixed["server"]["tls"]["enabled"]
+        .value_as::<bool>()
+        .unwrap());
+    assert_eq!(mixed["server"]["query"].value().unwrap(), "a=b");
+
+    let unspaced =
+        parse_string_with_options("url\n    http://x?a=b\nkey\n    abc==\n", &options).unwrap();
+    assert_eq!(unspaced["url"].value().unwrap(), "http://x?a=b");
+    assert_eq!(unspaced["key"].value().unwrap(), "abc==");
+
+    assert!(parse_string_with_options("server. = 1", &options).is_err());
+    assert!(parse_string_with_options("server.port =\n", &options).is_err());
+
+    let off = parse_string("port = 8080").unwrap();
+    assert!(off.has_key("port = 8080"));
+}
+
+#[test]
+fn dedent_several_levels() {
+    let config = parse_string("a\n    b\n        c\n            d\n    e\n").unwrap();
+    assert!(config.has_path(vec_into!["a", "b", "c", "d"]));
+    assert!(config.has_path(vec_into!["a", "e"]));
+}
+
+#[test]
+fn inline_lists() {
+    let options = ParseOptions::new()
+        .inline_lists(true)
+        .key_value_shorthand(true);
+    let config = parse_file_with_options("examples/lists.nccl", &options).unwrap();
+
+    let multi = parse_string("methods\n    GET\n    POST\n    PUT\n").unwrap();
+    assert_eq!(config["server"]["methods"], multi["methods"]);
+    assert_eq!(
+        config["server"]["ports"].keys_as::<i64>().unwrap(),
+        vec![80, 443]
+    );
+    assert_eq!(
+        config["server"]["names"]
+            .keys_as::<Option<String>>()
+            .unwrap(),
+        vec![
+            Some("example.com, www".into()),
+            Some(r"C:\srv".into()),
+            None
+        ]
+    );
+    assert!(config["server"].has_key("empty"));
+    assert_eq!(
+        config["client"]["methods"].keys_as::<String>().unwrap(),
+        vec!["GET", "HEAD", "OPTIONS"]
+    );
+    assert_eq!(
+        config["client"]["retry"].keys_as::<i64>().unwrap(),
+        vec![1, 2, 4]
+    );
+}
+
+#[test]
+fn inline_list_errors() {
+    let options = ParseOptions::new().inline_lists(true);
+    assert!(parse_string_with_options("a [b, c", &options).is_err());
+    assert!(parse_string_with_options("a [b, , c]", &options).is_err());
+    assert!(parse_string_with_options("a [\"b\" c]", &options).is_err());
+
+    let off = parse_string("pattern [a-z]").unwrap();
+    assert!(off.has_key("pattern [a-z]"));
+    let word = parse_string_with_options("pattern[a-z]", &options).unwrap();
+    assert!(word.has_key("pattern[a-z]"));
+}
+
+#[test]
+fn hash_in_bare_words() {
+    let config = parse_file("examples/hashes.nccl").unwrap();
+    assert_eq!(
+        config["docs"].value().unwrap(),
+        "https://example.com/docs#install"
+    );
+    assert_eq!(config["colour"].value().unwrap(), "#ff0000");
+    assert_eq!(config["channel"].value().unwrap(), "C# and F#");
+    assert_eq!(config["r#raw"].value().unwrap(), "r#not raw");
+    assert_eq!(
+        config["tagged"].keys_as::<String>().unwrap(),
+        vec!["one", "two"]
+    );
+
+    let options = ParseOptions::new().legacy_comments(true);
+    let config = parse_file_with_options("examples/hashes.nccl", &options).unwrap();
+    assert_eq!(config["docs"].value().unwrap(), "https://example.com/docs");
+    assert_eq!(config["colour"].value().unwrap(), "#ff0000");
+    assert_eq!(config["channel"].value().unwrap(), "C");
+}
+
+#[test]
+fn block_comments() {
+    let config = parse_file("examples/disabled.nccl").unwrap();
+    assert!(!config["server"].has_key("port"));
+    assert_eq!(
+        config["server"].keys_as::<String>().unwrap(),
+        vec!["domain", "root"]
+    );
+    assert_eq!(
+        config.keys_as::<String>().unwrap(),
+        vec!["server", "client"]
+    );
+    assert_eq!(config["client"]["timeout"].value_as::<i64>().unwrap(), 30);
+    assert_eq!(config["client"]["retries"].value_as::<i64>().unwrap(), 3);
+
+    assert!(parse_string("a\n    #[ never closed\n    b\n").is_err());
+    assert!(parse_string("a\n    b #[ note ]# c\n").is_err());
+}
+
+#[test]
+fn documents() {
+    let documents = parse_file_documents("examples/tenants.nccl").unwrap();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[0].line(), 2);
+    assert_eq!(documents[0]["tenant"].value().unwrap(), "acme");
+    assert_eq!(
+        documents[0]["server"]["port"].value_as::<i64>().unwrap(),
+        8080
+    );
+    assert_eq!(documents[1]["tenant"].value().unwrap(), "globex");
+    assert_eq!(
+        documents[1]["server"]["port"].value_as::<i64>().unwrap(),
+        9090
+    );
+
+    // without documents, --- is just a key
+    let single = parse_string("---\na\n    b\n").unwrap();
+    assert!(single.has_key("---"));
+
+    let single = parse_documents("a\n    b\n").unwrap();
+    assert_eq!(single.len(), 1);
+    assert_eq!(
+        single[0].clone().into_pair(),
+        parse_string("a\n    b\n").unwrap()
+    );
+}
+
+#[test]
+fn document_errors() {
+    let source = "a\n    b\n---\nc\n    d\n            e\n---\nf\n\tg\n";
+    let errors = parse_documents(source).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(format!("{}", errors[0]).contains("line 6"), "{}", errors[0]);
+
+    // trailing whitespace after the separator is fine, indentation is not
+    assert_eq!(
+        parse_documents("a\n    b\n---  \r\nc\n\td\n")
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(parse_documents("a\n    b\n  ---\n").is_err());
+}
+
+#[test]
+fn version_pragma() {
+    let config = parse_file("examples/version2.nccl").unwrap();
+    assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+    assert_eq!(
+        config["server"]["methods"].keys_as::<String>().unwrap(),
+        vec!["GET", "POST"]
+    );
+
+    let options = ParseOptions::new()
+        .key_value_shorthand(true)
+        .inline_lists(true);
+    let v1 = parse_string_with_options("#!nccl 1\r\nport = 8080\n", &options).unwrap();
+    assert!(v1.has_key("port = 8080"));
+
+    let default = parse_string("#!/usr/bin/env nccl\nport = 8080\n").unwrap();
+    assert!(default.has_key("port = 8080"));
+
+    let joined = parse_string("#!nccl2\nport = 8080\n").unwrap();
+    assert!(joined.has_key("port = 8080"));
+    assert!(parse_string("#!nccl-lint\na\n").unwrap().has_key("a"));
+
+    for source in &["#!nccl 3\na\n", "#!nccl\na\n", "#!nccl two\na\n"] {
+        let errors = parse_string(source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(format!("{}", errors[0]).contains("Unsupported nccl syntax version"));
+    }
+}
+
+#[test]
+fn strict_and_lenient() {
+    let strict = ParseOptions::strict();
+    assert!(parse_file_with_options("examples/config.nccl", &strict).is_ok());
+    assert!(parse_file_with_options("examples/comments.nccl", &strict).is_ok());
+
+    let junk = "juice\n    \"bone hurting juice\" junk\n";
+    let lenient = parse_string_with_options(junk, &ParseOptions::lenient()).unwrap();
+    assert_eq!(lenient["juice"].value().unwrap(), "bone hurting juice");
+    let errors = parse_string_with_options(junk, &strict).unwrap_err();
+    assert!(format!("{}", errors[0]).contains("junk"));
+
+    let spaces = "key\n    value  \n";
+    let lenient = parse_string(spaces).unwrap();
+    assert_eq!(lenient["key"].value().unwrap(), "value");
+    assert!(parse_string_with_options(spaces, &strict).is_err());
+    assert!(parse_string_with_options("key\n    \"value\" \n", &strict).is_err());
+
+    let empty = "server\n    port\nforgotten\n";
+    assert!(parse_string(empty).is_ok());
+    let errors = parse_string_with_options(empty, &strict).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(format!("{}", errors[0]).contains("line 3"));
+
+    let toggled = ParseOptions::strict().allow_trailing_text(true);
+    assert!(parse_string_with_options(junk, &toggled).is_ok());
+}
+
+#[test]
+fn indent_policy() {
+    assert!(parse_file("examples/indent.nccl").is_ok());
+    let file_wide = ParseOptions::new().indent_policy(IndentPolicy::FileWide);
+    let errors = parse_file_with_options("examples/indent.nccl", &file_wide).unwrap_err();
+    let message = format!("{}", errors[0]);
+    assert!(message.contains("Expected 4 spaces per level, found tabs"));
+    assert!(message.contains("on line 3 under if"));
+
+    assert!(parse_file("examples/mixed.nccl").is_err());
+    let tab_width = ParseOptions::new().indent_policy(IndentPolicy::TabWidth(4));
+    let config = parse_file_with_options("examples/mixed.nccl", &tab_width).unwrap();
+    assert_eq!(
+        config["sandwich"]["meat"].keys_as::<String>().unwrap(),
+        vec!["ham", "turkey"]
+    );
+    assert_eq!(config["sandwich"]["bread"].value().unwrap(), "rye");
+
+    let errors = parse_string("a\n\tb\n    c\n").unwrap_err();
+    assert!(format!("{}", errors[0]).contains("Expected tabs, found spaces"));
+
+    let zero = ParseOptions::new().indent_policy(IndentPolicy::TabWidth(0));
+    let errors = parse_string_with_options("a\n\tb\n\t\tc", &zero).unwrap_err();
+    assert!(format!("{}", errors[0]).contains("TabWidth must be at least 1"));
+}
+
+#[test]
+fn byte_order_marks() {
+    let bom = parse_file("examples/bom.nccl").unwrap();
+    let utf16 = parse_file("examples/utf16.nccl").unwrap();
+    for config in &[bom, utf16] {
+        assert_eq!(config["greeting"].value().unwrap(), "héllo");
+        assert_eq!(config["port"].value_as::<i64>().unwrap(), 8080);
+    }
+
+    let config = parse_string("\u{feff}key\n    value").unwrap();
+    assert_eq!(config["key"].value().unwrap(), "value");
+}
+
+#[test]
+fn unsupported_encodings() {
+    let errors = parse_file("examples/latin1.nccl").unwrap_err();
+    let message = format!("{}", errors[0]);
+    assert!(message.contains("Encoding { encoding: \"an 8-bit encoding such as Windows-1252\" }"));
+    assert!(message.contains("Save it as UTF-8"));
+    assert_eq!(errors[0].file(), Some("examples/latin1.nccl"));
+}
+
+#[test]
+fn includes() {
+    let config = parse_file("examples/include/main.nccl").unwrap();
+    assert_eq!(config["log"]["level"].value().unwrap(), "info");
+    assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+    assert!(config["server"]["tls"].value_as::<bool>().unwrap());
+    assert_eq!(config["server"]["domain"].value().unwrap(), "example.com");
+
+    let config = parse_string("@include examples/include/common.nccl").unwrap();
+    assert_eq!(config["log"]["level"].value().unwrap(), "info");
+
+    let config = parse_string("email\n    @include.example.com\n").unwrap();
+    assert_eq!(config["email"].value().unwrap(), "@include.example.com");
+}
+
+#[test]
+fn include_errors() {
+    let errors = parse_file("examples/include/cycle_a.nccl").unwrap_err();
+    let message = format!("{}", errors[0]);
+    assert!(message.contains(
+        "Include cycle: examples/include/cycle_a.nccl -> examples/include/cycle_b.nccl -> \
+         examples/include/cycle_a.nccl"
+    ));
+    assert_eq!(errors[0].file(), Some("examples/include/cycle_b.nccl"));
+    assert!(message.contains("included from examples/include/cycle_a.nccl on line 2"));
+
+    let errors = parse_file("examples/include/self.nccl").unwrap_err();
+    let message = format!("{}", errors[0]);
+    assert!(
+        message.contains("Include cycle: examples/include/self.nccl -> examples/include/self.nccl")
+    );
+    assert!(message.contains("on line 2"));
+    assert_eq!(errors[0].file(), Some("examples/include/self.nccl"));
+
+    let errors = parse_file("examples/include/missing.nccl").unwrap_err();
+    let message = format!("{}", errors[0]);
+    assert!(message.contains("Could not find examples/include/nowhere.nccl named by @include"));
+    assert!(message.contains("on line 2"));
+    assert_eq!(errors[0].file(), Some("examples/include/missing.nccl"));
+
+    let shallow = ParseOptions::new().max_include_depth(1);
+    let errors = parse_file_with_options("examples/include/main.nccl", &shallow).unwrap_err();
+    assert!(format!("{}", errors[0]).contains("more than 1 deep"));
+    assert_eq!(errors[0].file(), Some("examples/include/server.nccl"));
+}
+
+#[test]
+fn conf_d() {
+    let config = parse_dir("examples/conf.d", "*.nccl").unwrap();
+    assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+    assert_eq!(config["server"]["domain"].value().unwrap(), "example.com");
+    assert_eq!(config["log"].value().unwrap(), "debug");
+
+    let config = parse_dir("examples/conf.d", "2?-*").unwrap();
+    assert!(!config["server"].has_key("port"));
+    assert_eq!(config["log"].value().unwrap(), "debug");
+
+    let config = parse_dir("examples/conf.d", "1?-*").unwrap();
+    assert_eq!(config["log"].value().unwrap(), "info");
+
+    let everything = ParseOptions::new().skip_hidden_files(false);
+    let config = parse_dir_with_options("examples/conf.d", "*.nccl*", &everything).unwrap();
+    assert_eq!(config["log"].value().unwrap(), "trace");
+    assert_eq!(config["server"]["port"].value_as::<i64>().unwrap(), 8080);
+
+    let config = parse_dir("examples/conf.d.flags", "*.nccl").unwrap();
+    assert_eq!(
+        config.keys_as::<String>().unwrap(),
+        vec!["debug", "verbose"]
+    );
+}
+
+#[test]
+fn conf_d_errors() {
+    let errors = parse_dir("examples/conf.d.broken", "*.nccl").unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[0].file(),
+        Some("examples/conf.d.broken/20-tabs.nccl")
+    );
+    assert_eq!(
+        errors[1].file(),
+        Some("examples/conf.d.broken/30-quote.nccl")
+    );
+
+    let errors = parse_dir("examples/missing.d", "*.nccl").unwrap_err();
+    assert_eq!(errors[0].file(), Some("examples/missing.d"));
+}
+
+#[test]
+fn extends() {
+    let order = parse_file("examples/extends/order.nccl").unwrap();
+    assert_eq!(
+        order["sandwich"]["meat"].keys_as::<String>().unwrap(),
+        vec!["bologne", "ham", "turkey"]
+    );
+    assert_eq!(
+        order["sandwich"]["cheese"].keys_as::<String>().unwrap(),
+        vec!["provolone", "cheddar", "swiss"]
+    );
+    assert_eq!(order["sandwich"]["bread"].value().unwrap(), "rye");
+    assert!(order["hello"]["world"].has_key("panama"));
+
+    let errors = parse_file("examples/extends/loop_a.nccl").unwrap_err();
+    assert!(format!("{}", errors[0]).contains(
+        "Inheritance cycle: examples/extends/loop_a.nccl -> examples/extends/loop_b.nccl -> \
+         examples/extends/loop_a.nccl"
+    ));
+
+    let errors = parse_file("examples/extends/late.nccl").unwrap_err();
+    assert!(format!("{}", errors[0]).contains("on line 4"));
+}
+
+#[test]
+fn unset_and_replace() {
+    let config = parse_file("examples/extends/vegetarian.nccl").unwrap();
+    assert!(config["hello"].has_key("world"));
+    assert!(!config["hello"].has_key("friends"));
+    assert_eq!(
+        config["sandwich"]["meat"].keys_as::<String>().unwrap(),
+        vec!["tofu"]
+    );
+    assert_eq!(
+        config["sandwich"]["cheese"].keys_as::<String>().unwrap(),
+        vec!["gouda"]
+    );
+
+    let parent = parse_file("examples/inherit.nccl").unwrap();
+    let config = parse_file_with("examples/extends/no_ham.nccl", parent).unwrap();
+    assert_eq!(
+        config["sandwich"]["meat"].keys_as::<String>().unwrap(),
+        vec!["bologne"]
+    );
+    assert_eq!(config["sandwich"]["cheese"].value().unwrap(), "brie");
+
+    let errors = parse_file("examples/extends/no_ham.nccl").unwrap_err();
+    assert!(format!("{}", errors[0]).contains("Can't unset sandwich/meat/ham, it isn't set"));
+
+    let strict = ParseOptions::strict();
+    let config = parse_string_with_options("a\n    1\nb\n    2\n@unset a\n", &strict).unwrap();
+    assert!(!config.has_key("a"));
+    assert_eq!(config["b"].value_as::<i64>().unwrap(), 2);
+    let config = parse_string_with_options("a\n    1\n@unset a\n", &strict).unwrap();
+    assert!(!config.has_key("a"));
+}
+
+#[test]
+fn merge_strategies() {
+    let parent = parse_file("examples/inherit.nccl").unwrap();
+    let child = parse_file("examples/inherit2.nccl").unwrap();
+    let meat = |strategy, other: &Pair| {
+        let mut config = parent.clone();
+        config.merge(other.clone(), strategy).unwrap();
+        config["sandwich"]["meat"].keys_as::<String>().unwrap()
+    };
+
+    assert_eq!(
+        meat(MergeStrategy::DeepMerge, &child),
+        vec!["bologne", "ham", "turkey"]
+    );
+    assert_eq!(meat(MergeStrategy::ReplaceLeaves, &child), vec!["turkey"]);
+    assert_eq!(
+        meat(MergeStrategy::AppendLists, &parent),
+        vec!["bologne", "ham", "bologne", "ham"]
+    );
+    assert_eq!(
+        meat(MergeStrategy::UnionLists, &parent),
+        vec!["bologne", "ham"]
+    );
+    assert_eq!(
+        meat(MergeStrategy::ErrorOnConflict, &parent),
+        vec!["bologne", "ham"]
+    );
+
+    let mut config = parent.clone();
+    let error = config
+        .merge(child.clone(), MergeStrategy::ErrorOnConflict)
+        .unwrap_err();
+    assert!(format!("{}", error)
+        .contains("Conflicting values for hello/world: panama and alaska, neighbor"));
+
+    let mut config = parent.clone();
+    let mut seen = vec![];
+    let result = config.merge_with(child, MergeStrategy::DeepMerge, |path, ours, theirs| {
+        seen.push((
+            path.to_vec(),
+            ours.keys_as::<String>().unwrap(),
+            theirs.keys_as::<String>().unwrap(),
+        ));
+        if path[0] == Value::from("sandwich") {
+            Err(NcclError::new(ErrorKind::Conflict, "no sandwiches", 0))
+        } else {
+            Ok(())
+        }
+    });
+    assert!(result.is_err());
+    assert_eq!(seen.len(), 3);
+    assert_eq!(
+        seen[2],
+        (
+            vec![Value::from("sandwich"), Value::from("meat")],
+            vec!["bologne".to_string(), "ham".to_string()],
+            vec!["turkey".to_string()]
+        )
+    );
+
+    let value = parse_string("a\n    1\n").unwrap();
+    let subtree = parse_string("a\n    b\n        c\n").unwrap();
+    let mut config = value.clone();
+    let error = config
+        .merge(subtree.clone(), MergeStrategy::ErrorOnConflict)
+        .unwrap_err();
+    assert!(format!("{}", error).contains("Conflicting values for a: 1 and b"));
+
+    let mut config = subtree.clone();
+    let mut conflicts = 0;
+    config
+        .merge_with(value.clone(), MergeStrategy::DeepMerge, |_, _, _| {
+            conflicts += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(conflicts, 1);
+
+    let mut config = value;
+    config.merge(subtree, MergeStrategy::ReplaceLeaves).unwrap();
+    assert_eq!(config["a"]["b"].value().unwrap(), "c");
+    assert!(!config["a"].has_key(1));
+
+    let flags = parse_string("a\nb\n").unwrap();
+    let more = parse_string("c\n").unwrap();
+    for &strategy in &[
+        MergeStrategy::DeepMerge,
+        MergeStrategy::ReplaceLeaves,
+        MergeStrategy::AppendLists,
+        MergeStrategy::UnionLists,
+        MergeStrategy::ErrorOnConflict,
+    ] {
+        let mut config = flags.clone();
+        config.merge(more.clone(), strategy).unwrap();
+        assert_eq!(config.keys_as::<String>().unwrap(), vec!["a", "b", "c"]);
+    }
+}
+
+#[test]
+fn config_builder() {
+    let mut defaults = Pair::new("__top_level__");
+    defaults.add_slice(&[Value::from("log"), Value::from("info")]);
+
+    let builder = ConfigBuilder::new()
+        .add_default(defaults)
+        .add_file("examples/layers/system.nccl")
+        .add_optional_file("examples/layers/missing.nccl")
+        .add_optional_file("examples/layers/user.nccl");
+    let config = builder.build().unwrap();
+    assert_eq!(
+        config["server"]["port"].keys_as::<i64>().unwrap(),
+        vec![8080]
+    );
+    assert_eq!(config["server"]["domain"].value().unwrap(), "example.com");
+    assert_eq!(config["log"].value().unwrap(), "info");
+
+    let port = [Value::from("server"), Value::from("port")];
+    assert_eq!(config.source_of(&port), Some("examples/layers/user.nccl"));
+    let domain = [Value::from("server"), Value::from("domain")];
+    assert_eq!(
+        config.source_of(&domain),
+        Some("examples/layers/system.nccl")
+    );
+    assert_eq!(config.source_of(&[Value::from("log")]), Some("defaults"));
+    assert_eq!(config.source_of(&[Value::from("missing")]), None);
+    let replaced = [Value::from("server"), Value::from("port"), Value::from(80)];
+    assert_eq!(config.source_of(&replaced), None);
+
+    let config = ConfigBuilder::new()
+        .strategy(MergeStrategy::UnionLists)
+        .add_file("examples/layers/system.nccl")
+        .add_file("examples/layers/user.nccl")
+        .build()
+        .unwrap();
+    assert_eq!(
+        config["server"]["port"].keys_as::<i64>().unwrap(),
+        vec![80, 443, 8080]
+    );
+
+    let config = ConfigBuilder::new()
+        .add_default(parse_string("debug\n").unwrap())
+        .add_file("examples/layers/user.nccl")
+        .add_string("verbose\n")
+        .build()
+        .unwrap();
+    assert_eq!(
+        config.keys_as::<String>().unwrap(),
+        vec!["debug", "server", "verbose"]
+    );
+    assert_eq!(config.source_of(&[Value::from("debug")]), Some("defaults"));
+    assert_eq!(config.source_of(&[Value::from("verbose")]), Some("string"));
+    assert_eq!(
+        config.source_of(&[Value::from("server")]),
+        Some("examples/layers/user.nccl")
+    );
+
+    let errors = ConfigBuilder::new()
+        .add_file("examples/layers/missing.nccl")
+        .add_file("examples/tabs.nccl")
+        .build()
+        .unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].file(), Some("examples/layers/missing.nccl"));
+}
+
+#[test]
+fn env_source() {
+    let vars = vec![
+        ("NCCL_TEST_SERVER__PORT".to_string(), "8080".to_string()),
+        (
+            "NCCL_TEST_SERVER__DOMAIN".into(),
+            "a.example.com,b.example.com".into(),
+        ),
+        ("NCCL_TEST_LOG_LEVEL".into(), "debug".into()),
+        ("NCCL_TEST_TLS__ENABLED".into(), "true".into()),
+        ("NCCL_TEST_HOSTS".into(), "a,b".into()),
+        ("NCCL_TEST___BROKEN".into(), "x".into()),
+        ("OTHER_SERVER__PORT".into(), "1".into()),
+    ];
+    let env = EnvSource::new("NCCL_TEST_").vars(vars.clone());
+    let mut config = parse_file("examples/config.nccl").unwrap();
+    config
+        .merge(env.to_pair(), MergeStrategy::ReplaceLeaves)
+        .unwrap();
+    assert_eq!(
+        config["server"]["port"].keys_as::<i64>().unwrap(),
+        vec![8080]
+    );
+    assert_eq!(
+        config["server"]["domain"].keys_as::<String>().unwrap(),
+        vec!["a.example.com", "b.example.com"]
+    );
+    assert_eq!(config["server"]["root"].value().unwrap(), "/var/www/html");
+    assert_eq!(config["log_level"].value().unwrap(), "debug");
+    assert!(config["tls"]["enabled"].value_as::<bool>().unwrap());
+    assert!(!config.has_key("_broken"));
+    assert!(!config.has_key("broken"));
+    assert_eq!(config["hosts"].keys_as::<String>().unwrap(), vec!["a", "b"]);
+
+    let env = EnvSource::new("NCCL_TEST_")
+        .separator("_")
+        .lowercase(false)
+        .lists(false)
+        .vars(vars);
+    let pair = env.to_pair();
+    assert_eq!(pair["LOG"]["LEVEL"].value().unwrap(), "debug");
+    assert!(!pair.has_key("SERVER"));
+    assert_eq!(pair["HOSTS"].value().unwrap(), "a,b");
+
+    let config = ConfigBuilder::new()
+        .add_file("examples/config.nccl")
+        .add_source(
+            EnvSource::new("NCCL_TEST_")
+                .vars(vec![("NCCL_TEST_SERVER__PORT".into(), "8080".into())]),
+        )
+        .build()
+        .unwrap();
+    let port = [Value::from("server"), Value::from("port")];
+    assert_eq!(config.source_of(&port), Some("environment (NCCL_TEST_*)"));
+}
+
+#[test]
+fn overrides() {
+    let mut config = parse_file("examples/config.nccl").unwrap();
+    apply_overrides(
+        &mut config,
+        &[
+            "server.port=8080",
+            "server.domain+=api.example.com",
+            "server.domain-=www.example.com",
+            "server.tls.enabled = true",
+            "server.root-=",
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        config["server"]["port"].keys_as::<i64>().unwrap(),
+        vec![8080]
+    );
+    assert_eq!(
+        config["server"]["domain"].keys_as::<String>().unwrap(),
+        vec!["example.com", "api.example.com"]
+    );
+    assert!(config["server"]["tls"]["enabled"]
+        .value_as::<bool>()
+        .unwrap());
+    assert!(!config["server"].has_key("root"));
+
+    let errors = apply_overrides(
+        &mut config,
+        &[
+            "server.port",
+            ".port=1",
+            "server.port+=",
+            "server.root-=",
+            "server.port-=80",
+            "server.port+=8443",
+        ],
+    )
+    .unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| format!("{}", e)).collect();
+    assert_eq!(messages.len(), 5);
+    assert!(messages[0].contains("Override `server.port` failed: expected `=`, `+=` or `-=`"));
+    assert!(messages[1].contains("expected a key"));
+    assert!(messages[2].contains("expected a value after `+=`"));
+    assert!(messages[3].contains("server/root isn't set"));
+    assert!(messages[4].contains("Override `server.port-=80` failed: server/port has no value 80"));
+    assert_eq!(
+        config["server"]["port"].keys_as::<i64>().unwrap(),
+        vec![8080, 8443]
+    );
+}
+
+#[test]
+fn interpolation() {
+    let vars = Variables::Fixed(vec![
+        ("USER".into(), "ferris".into()),
+        ("PORT".into(), "8080".into()),
+        ("EMPTY".into(), "".into()),
+    ]);
+    let options = ParseOptions::new().interpolation(vars.clone());
+    let config = parse_file_with_options("examples/interpolate.nccl", &options).unwrap();
+    assert_eq!(config["data"].value().unwrap(), "/home/ferris/data");
+    assert_eq!(config["password"].value().unwrap(), "changeme");
+    assert_eq!(config["price"].value().unwrap(), "$5");
+    assert_eq!(config["pattern"].value().unwrap(), r"^\$\{[A-Z]+\}$");
+    assert_eq!(config["port"].value_as::<i64>().unwrap(), 8080);
+
+    let config = parse_file("examples/interpolate.nccl").unwrap();
+    assert_eq!(config["data"].value().unwrap(), "/home/${USER}/data");
+
+    let error = |source: &str| {
+        let errors = parse_string_with_options(source, &options).unwrap_err();
+        format!("{}", errors[0])
+    };
+    assert!(error("a\n    ${MISSING}").contains("MISSING is not set"));
+    assert!(error("a\n    ${MISSING}").contains("on line 2"));
+    assert!(error("a\n    b\nc\n    ${EMPTY:?must be set}").contains("EMPTY: must be set"));
+    assert!(error("a\n    b\nc\n    ${EMPTY:?must be set}").contains("on line 4"));
+    assert!(error("a\n    ${USER").contains("Unterminated"));
+    assert!(error("a\n    ${1USER}").contains("Invalid variable name"));
+    assert!(error("a\n    ${USER:+x}").contains("Expected :- or :?"));
+    assert!(error("a\n    ${MISSING:-${USER}}").contains("can't contain ${...}"));
+    assert!(error("a\n    ${MISSING:?${USER}}").contains("can't contain ${...}"));
+    assert!(parse_string_with_options("a\n    ${EMPTY}x", &options).is_ok());
+}
+
+#[test]
+fn references() {
+    let config = parse_file("examples/refs.nccl").unwrap();
+    assert_eq!(config["cdn"]["domain"].value().unwrap(), "example.com");
+    assert_eq!(config["mirror"]["domain"].value().unwrap(), "example.com");
+    assert_eq!(
+        config["mirror"]["port"].keys_as::<i64>().unwrap(),
+        vec![8443, 443]
+    );
+    assert_eq!(config["backup"]["domain"].value().unwrap(), "example.com");
+    assert_eq!(
+        config["backup"]["port"].keys_as::<i64>().unwrap(),
+        vec![8443, 443]
+    );
+
+    let errors = parse_file("examples/refs_cycle.nccl").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(format!("{}", errors[0]).contains("Reference cycle: a -> b -> c/d -> a"));
+
+    let errors =
+        parse_string("server\n    domain\n        example.com\ncdn\n    @ref server/domian\n")
+            .unwrap_err();
+    let message = format!("{}", errors[0]);
+    assert!(message.contains("cdn refers to server/domian, which isn't set"));
+    assert!(message.contains("on line 5"));
+}